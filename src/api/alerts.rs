@@ -0,0 +1,427 @@
+use chrono::{DateTime, Duration, Utc};
+use juniper::{GraphQLEnum, GraphQLObject};
+use serde::{Deserialize, Serialize};
+use slog::{info, warn};
+
+use super::gql::Context;
+use super::model::{self, BragiInfo, BragiStatus, ElasticsearchIndexInfo};
+use super::trends::CountDrop;
+use crate::error;
+use crate::settings::{AlertCondition, AlertRule};
+use crate::state::State;
+use crate::utils;
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Copy, GraphQLEnum)]
+#[serde(rename_all = "camelCase")]
+pub enum AlertState {
+    Firing,
+    Resolved,
+    /// The alert changes state too often. This is only notified, the alert itself stays firing or
+    /// resolved.
+    Flapping,
+}
+
+/// The state of an alert rule for an environment.
+#[derive(Debug, Deserialize, Serialize, Clone, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct Alert {
+    pub rule: String,
+    /// The kind of condition of the rule, eg 'unreachable'
+    pub kind: String,
+    pub environment: String,
+    pub state: AlertState,
+    /// What is, or was, wrong.
+    pub summary: String,
+    /// The coverages the alert is about, eg those with an index too old, or a document count
+    /// drop (none for the conditions about the whole environment).
+    #[serde(default)]
+    pub coverages: Vec<String>,
+    /// The time at which the alert entered its current state.
+    pub since: DateTime<Utc>,
+    /// The time at which the current state was acknowledged, if it was.
+    #[serde(default)]
+    pub acknowledged_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub acknowledgement: Option<String>,
+}
+
+/// The response body for alerts
+#[derive(Debug, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertsResponseBody {
+    alerts: Vec<Alert>,
+    alerts_count: i32,
+}
+
+impl From<Vec<Alert>> for AlertsResponseBody {
+    fn from(alerts: Vec<Alert>) -> Self {
+        let alerts_count = utils::count(alerts.len());
+        Self {
+            alerts,
+            alerts_count,
+        }
+    }
+}
+
+impl Alert {
+    /// The key identifying the notification of an episode of the alert (a state entered at a
+    /// given time), so that it is not notified again, eg after a restart. An alert firing again
+    /// later is a new episode, which is notified.
+    pub fn dedup_key(&self) -> String {
+        let state = match self.state {
+            AlertState::Firing => "firing",
+            AlertState::Resolved => "resolved",
+            AlertState::Flapping => "flapping",
+        };
+        format!(
+            "{}/{}/{}/{}",
+            self.rule,
+            self.environment,
+            state,
+            self.since.timestamp()
+        )
+    }
+}
+
+/// How an alert rule behaved over the recent probes of an environment.
+#[derive(Debug, Default, Clone)]
+pub struct Tracking {
+    /// Whether the condition was met by the last probe with a known outcome.
+    pub failing: bool,
+    /// The number of consecutive probes with the same outcome as the last one.
+    pub streak: u32,
+    /// Whether the alert changes state too often to be notified.
+    pub flapping: bool,
+}
+
+impl Tracking {
+    fn record(&mut self, failing: bool) {
+        if self.failing == failing {
+            self.streak = self.streak.saturating_add(1);
+        } else {
+            self.failing = failing;
+            self.streak = 1;
+        }
+    }
+}
+
+// The outcome of a rule for a probe, with the summary and the coverages of a firing alert. If the
+// outcome is unknown (eg the indices could not be retrieved), the alert stays in its current
+// state.
+enum Outcome {
+    Firing(String, Vec<String>),
+    Clear,
+    Unknown,
+}
+
+/// Evaluate the alert rules for the probe of an environment, store the alerts which fired or
+/// resolved, and return those to notify. An alert fires (or resolves) once its condition is met
+/// (or not) by the number of consecutive probes given by its rule. An alert changing state too
+/// often is flapping: this is notified once, then its changes of state are not notified until it
+/// is stable, when its current state is notified.
+pub async fn evaluate(
+    state: &State,
+    previous: Option<&BragiInfo>,
+    info: &BragiInfo,
+    drops: &[CountDrop],
+) -> Vec<Alert> {
+    let mut notifications = Vec::new();
+    for rule in state
+        .settings
+        .alerts
+        .iter()
+        .filter(|rule| rule.applies_to(&info.environment))
+    {
+        // A rule which cannot be evaluated does not prevent the others from being evaluated.
+        match evaluate_rule(state, rule, previous, info, drops).await {
+            Ok(notification) => notifications.extend(notification),
+            Err(err) => warn!(
+                state.logger,
+                "Could not evaluate alert {} in {}: {}", rule.name, info.environment, err
+            ),
+        }
+    }
+    notifications
+}
+
+// Evaluate an alert rule for the probe of an environment, store the alert if it fired or
+// resolved, and return it if it is to be notified.
+async fn evaluate_rule(
+    state: &State,
+    rule: &AlertRule,
+    previous: Option<&BragiInfo>,
+    info: &BragiInfo,
+    drops: &[CountDrop],
+) -> Result<Option<Alert>, error::Error> {
+    let alerting = &state.settings.alerting;
+    let flap_window = alerting.flap_window;
+    let mut notification = None;
+    let outcome = outcome(rule, previous, info, drops);
+    let mut tracking = state
+        .db
+        .alert_tracking(&rule.name, &info.environment)
+        .await?;
+    match outcome {
+        Outcome::Firing(..) => tracking.record(true),
+        Outcome::Clear => tracking.record(false),
+        Outcome::Unknown => {}
+    }
+    let current = state.db.alert(&rule.name, &info.environment).await?;
+    let firing = current
+        .as_ref()
+        .map_or(false, |alert| alert.state == AlertState::Firing);
+    let transition = match (outcome, current.as_ref()) {
+        (Outcome::Firing(summary, coverages), _)
+            if !firing && tracking.streak >= rule.fire_after =>
+        {
+            Some(Alert {
+                rule: rule.name.clone(),
+                kind: String::from(rule.condition.kind()),
+                environment: info.environment.clone(),
+                state: AlertState::Firing,
+                summary,
+                coverages,
+                since: info.updated_at,
+                acknowledged_at: None,
+                acknowledgement: None,
+            })
+        }
+        (Outcome::Clear, Some(alert)) if firing && tracking.streak >= rule.resolve_after => {
+            Some(Alert {
+                state: AlertState::Resolved,
+                since: info.updated_at,
+                acknowledged_at: None,
+                acknowledgement: None,
+                ..alert.clone()
+            })
+        }
+        _ => None,
+    };
+
+    let transitions = state
+        .db
+        .alert_transitions(&rule.name, &info.environment, info.updated_at - flap_window)
+        .await?;
+    match transition {
+        Some(alert) => {
+            state.db.upsert_alert(&alert).await?;
+            state.db.insert_alert_transition(&alert).await?;
+            // The transitions in the window, including this one.
+            let changes = transitions.len() + 1;
+            if tracking.flapping {
+                info!(
+                    state.logger,
+                    "Alert {} {:?} in {} not notified: flapping",
+                    alert.rule,
+                    alert.state,
+                    alert.environment
+                );
+            } else if changes >= alerting.flap_threshold as usize {
+                tracking.flapping = true;
+                notification = Some(Alert {
+                    state: AlertState::Flapping,
+                    summary: format!(
+                        "changed state at least {} times within {} minutes",
+                        alerting.flap_threshold,
+                        flap_window.num_minutes()
+                    ),
+                    ..alert
+                });
+            } else {
+                notification = Some(alert);
+            }
+        }
+        None if tracking.flapping && transitions.is_empty() => {
+            tracking.flapping = false;
+            notification = current;
+        }
+        None => {}
+    }
+    state
+        .db
+        .upsert_alert_tracking(&rule.name, &info.environment, &tracking)
+        .await?;
+
+    Ok(notification)
+}
+
+fn outcome(
+    rule: &AlertRule,
+    previous: Option<&BragiInfo>,
+    info: &BragiInfo,
+    drops: &[CountDrop],
+) -> Outcome {
+    match &rule.condition {
+        AlertCondition::Unreachable => match info.status {
+            BragiStatus::BragiNotAvailable => {
+                Outcome::Firing(String::from("bragi did not answer"), Vec::new())
+            }
+            _ => Outcome::Clear,
+        },
+        AlertCondition::ElasticsearchUnavailable => match info.status {
+            BragiStatus::Available => Outcome::Clear,
+            BragiStatus::ElasticsearchNotAvailable => {
+                Outcome::Firing(String::from("elasticsearch is not available"), Vec::new())
+            }
+            BragiStatus::BragiNotAvailable => Outcome::Unknown,
+        },
+        AlertCondition::IndexAge {
+            coverage,
+            place_type,
+            max_age_days,
+        } => match info.indices() {
+            Some(indices) => stale_indices(
+                indices,
+                coverage,
+                place_type.as_deref(),
+                Duration::days(i64::from(*max_age_days)),
+                info.updated_at,
+            ),
+            None => Outcome::Unknown,
+        },
+        AlertCondition::DocumentCountDrop => match info.indices() {
+            Some(_) if drops.is_empty() => Outcome::Clear,
+            Some(_) => {
+                let mut coverages = drops
+                    .iter()
+                    .map(|drop| drop.coverage.clone())
+                    .collect::<Vec<_>>();
+                coverages.sort();
+                coverages.dedup();
+                Outcome::Firing(
+                    drops
+                        .iter()
+                        .map(|drop| {
+                            format!(
+                                "document count of {} dropped by {:.1}% ({} -> {})",
+                                drop.index, drop.drop_percent, drop.previous_count, drop.count
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    coverages,
+                )
+            }
+            None => Outcome::Unknown,
+        },
+        AlertCondition::VersionChanged => {
+            let previous_version = previous
+                .map(|previous| previous.version.as_str())
+                .unwrap_or_default();
+            if info.version.is_empty() {
+                Outcome::Unknown
+            } else if !previous_version.is_empty() && previous_version != info.version {
+                Outcome::Firing(
+                    format!(
+                        "bragi version changed from {} to {}",
+                        previous_version, info.version
+                    ),
+                    Vec::new(),
+                )
+            } else {
+                Outcome::Clear
+            }
+        }
+    }
+}
+
+// The alert fires if the newest index of any place type of the coverage is too old, or if there
+// is no index for the coverage.
+fn stale_indices(
+    indices: &[ElasticsearchIndexInfo],
+    coverage: &str,
+    place_type: Option<&str>,
+    max_age: Duration,
+    now: DateTime<Utc>,
+) -> Outcome {
+    let newest = model::newest_indices_by(
+        indices.iter().filter(|index| {
+            index.coverage == coverage
+                && place_type.map_or(true, |place_type| index.place_type == place_type)
+        }),
+        |index| index.place_type.as_str(),
+    );
+
+    if newest.is_empty() {
+        return Outcome::Firing(
+            format!("no index for coverage {}", coverage),
+            vec![String::from(coverage)],
+        );
+    }
+
+    let mut stale = newest
+        .values()
+        .filter(|index| now - index.date > max_age)
+        .map(|index| {
+            format!(
+                "{} is {} days old",
+                index.label,
+                (now - index.date).num_days()
+            )
+        })
+        .collect::<Vec<_>>();
+    if stale.is_empty() {
+        Outcome::Clear
+    } else {
+        stale.sort();
+        Outcome::Firing(stale.join(", "), vec![String::from(coverage)])
+    }
+}
+
+/// Retrieve the alerts of an environment (or all environments), optionally only those in the
+/// given state.
+pub async fn alerts(
+    context: &Context,
+    environment: Option<String>,
+    state: Option<AlertState>,
+) -> Result<AlertsResponseBody, error::Error> {
+    if let Some(environment) = &environment {
+        context.state.settings.environment(environment)?;
+    }
+
+    let alerts = context
+        .state
+        .db
+        .alerts(environment.as_deref())
+        .await?
+        .into_iter()
+        .filter(|alert| state.map_or(true, |state| alert.state == state))
+        .collect::<Vec<_>>();
+
+    Ok(AlertsResponseBody::from(alerts))
+}
+
+/// Acknowledge a firing alert, and return it.
+pub async fn acknowledge(
+    context: &Context,
+    rule: &str,
+    environment: &str,
+    comment: Option<String>,
+) -> Result<Alert, error::Error> {
+    context.state.settings.alert_rule(rule)?;
+    context.state.settings.environment(environment)?;
+    let not_firing = || error::Error::AlertNotFiring {
+        rule: String::from(rule),
+        environment: String::from(environment),
+    };
+
+    let alert = context
+        .state
+        .db
+        .alert(rule, environment)
+        .await?
+        .filter(|alert| alert.state == AlertState::Firing)
+        .ok_or_else(not_firing)?;
+    let now = Utc::now();
+    context
+        .state
+        .db
+        .insert_acknowledgement(&alert, now, comment.as_deref())
+        .await?;
+
+    Ok(Alert {
+        acknowledged_at: Some(now),
+        acknowledgement: comment,
+        ..alert
+    })
+}
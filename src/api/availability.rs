@@ -0,0 +1,203 @@
+use chrono::{DateTime, Duration, Utc};
+use juniper::GraphQLObject;
+use serde::Serialize;
+use std::convert::TryFrom;
+use std::ops::Add;
+
+use super::gql::Context;
+use crate::db::{Db, Sample};
+use crate::error;
+use crate::utils;
+
+/// The availability of a service over a window of time.
+#[derive(Debug, Serialize, Clone, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceAvailability {
+    /// The percentage of the observed time during which the service was available, if the
+    /// service was observed at all.
+    pub uptime: Option<f64>,
+    /// The time during which the service was not available, in seconds.
+    pub downtime: f64,
+    /// The time during which the state of the service is known, in seconds.
+    pub observed: f64,
+    /// The number of times the service became unavailable.
+    pub outages: i32,
+}
+
+/// The time during which a service was observed, the time during which it was unavailable (both
+/// in seconds), and its number of outages.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Uptime {
+    pub observed: i64,
+    pub downtime: i64,
+    pub outages: i64,
+}
+
+impl Uptime {
+    /// The part of the uptime of a period of 'length' seconds falling within 'overlap' seconds of
+    /// it, assuming it is spread evenly over the period.
+    pub fn prorated(self, overlap: i64, length: i64) -> Self {
+        if overlap >= length {
+            return self;
+        }
+        let part = |value: i64| (value * overlap.max(0) + length / 2) / length;
+        Uptime {
+            observed: part(self.observed),
+            downtime: part(self.downtime),
+            outages: part(self.outages),
+        }
+    }
+}
+
+impl Add for Uptime {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Uptime {
+            observed: self.observed + other.observed,
+            downtime: self.downtime + other.downtime,
+            outages: self.outages + other.outages,
+        }
+    }
+}
+
+impl From<Uptime> for ServiceAvailability {
+    fn from(uptime: Uptime) -> Self {
+        ServiceAvailability {
+            uptime: if uptime.observed > 0 {
+                Some(100.0 * (uptime.observed - uptime.downtime) as f64 / uptime.observed as f64)
+            } else {
+                None
+            },
+            downtime: uptime.downtime as f64,
+            observed: uptime.observed as f64,
+            outages: i32::try_from(uptime.outages).unwrap_or(i32::MAX),
+        }
+    }
+}
+
+/// The availability of bragi and its elasticsearch for an environment.
+#[derive(Debug, Serialize, Clone, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct Availability {
+    pub environment: String,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub bragi: ServiceAvailability,
+    pub elasticsearch: ServiceAvailability,
+}
+
+/// Compute the availability of an environment over the window (by default '30d') ending at 'to'
+/// (by default, now).
+pub async fn availability(
+    context: &Context,
+    environment: &str,
+    window: Option<String>,
+    to: Option<DateTime<Utc>>,
+) -> Result<Availability, error::Error> {
+    let env = context.state.settings.environment(environment)?;
+    let window = utils::parse_duration(window.as_deref().unwrap_or("30d"))?;
+    let to = to.unwrap_or_else(Utc::now);
+    let max_gap = max_gap(context.state.settings.poller.interval);
+
+    let from = utils::time_before(to, window)?;
+
+    compute(&context.state.db, &env.name, from, to, max_gap).await
+}
+
+/// The longest time a sample is considered valid: Beyond that, we don't know the state of the
+/// environment (eg bragi-status itself was not running).
+pub fn max_gap(interval: u64) -> Duration {
+    Duration::seconds(2 * i64::try_from(interval).unwrap_or(i64::MAX / 2))
+}
+
+/// Compute the availability of an environment from the probe results stored between 'from' and
+/// 'to', and from the aggregates of older probe results, prorated when they straddle 'from' or
+/// 'to'. Each probe result is considered valid until the next one, or at most for 'max_gap'.
+pub async fn compute(
+    db: &Db,
+    environment: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    max_gap: Duration,
+) -> Result<Availability, error::Error> {
+    let samples = db.samples(environment, from, to).await?;
+    let (bragi, elasticsearch) = summarize(&samples, to.timestamp(), max_gap.num_seconds());
+    let (bragi_aggregated, elasticsearch_aggregated) =
+        db.uptime_aggregates(environment, from, to).await?;
+
+    Ok(Availability {
+        environment: String::from(environment),
+        from,
+        to,
+        bragi: ServiceAvailability::from(bragi + bragi_aggregated),
+        elasticsearch: ServiceAvailability::from(elasticsearch + elasticsearch_aggregated),
+    })
+}
+
+/// Compute the uptime of bragi and of its elasticsearch from a list of samples in chronological
+/// order, the last one being valid until 'to' (in seconds since the epoch).
+pub fn summarize(samples: &[Sample], to: i64, max_gap: i64) -> (Uptime, Uptime) {
+    // bragi is available unless it did not answer. Its elasticsearch is only known if bragi
+    // answered.
+    let bragi = samples
+        .iter()
+        .map(|sample| {
+            (
+                sample.recorded_at,
+                Some(sample.status != "bragiNotAvailable"),
+            )
+        })
+        .collect::<Vec<_>>();
+    let elasticsearch = samples
+        .iter()
+        .map(|sample| (sample.recorded_at, elasticsearch_up(sample)))
+        .collect::<Vec<_>>();
+
+    (
+        service_uptime(&bragi, to, max_gap),
+        service_uptime(&elasticsearch, to, max_gap),
+    )
+}
+
+fn elasticsearch_up(sample: &Sample) -> Option<bool> {
+    match sample.status.as_str() {
+        "available" => Some(true),
+        "elasticsearchNotAvailable" => Some(false),
+        _ => None,
+    }
+}
+
+// Compute the uptime from a list of (timestamp, state) in chronological order, where the state is
+// None if unknown.
+fn service_uptime(samples: &[(i64, Option<bool>)], to: i64, max_gap: i64) -> Uptime {
+    let mut up = 0;
+    let mut down = 0;
+    let mut outages = 0;
+    let mut previous_up = true;
+
+    for (idx, (timestamp, state)) in samples.iter().enumerate() {
+        let end = samples.get(idx + 1).map(|(next, _)| *next).unwrap_or(to);
+        let span = (end - timestamp).min(max_gap).max(0);
+        match state {
+            Some(true) => {
+                up += span;
+                previous_up = true;
+            }
+            Some(false) => {
+                down += span;
+                if previous_up {
+                    outages += 1;
+                }
+                previous_up = false;
+            }
+            None => {}
+        }
+    }
+
+    Uptime {
+        observed: up + down,
+        downtime: down,
+        outages,
+    }
+}
@@ -0,0 +1,103 @@
+use futures::stream::{self, StreamExt};
+use serde::Deserialize;
+use std::convert::Infallible;
+use warp::http::StatusCode;
+use warp::sse::ServerSentEvent;
+use warp::Reply;
+
+use super::model::BragiInfo;
+use crate::state::State;
+
+/// A change in the status of an environment, identified by its position in the history of status
+/// changes, unless it could not be stored.
+#[derive(Debug, Clone)]
+pub struct StatusChange {
+    pub id: Option<i64>,
+    pub info: BragiInfo,
+}
+
+/// The query string of the events stream.
+#[derive(Debug, Default, Deserialize)]
+pub struct EventsQuery {
+    /// Only stream the changes of this environment.
+    pub environment: Option<String>,
+}
+
+/// Stream the status changes as server-sent events. If the client resumes the stream, the changes
+/// following the last one it received are replayed from the history first.
+pub async fn events(
+    state: State,
+    query: EventsQuery,
+    last_event_id: Option<i64>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    if let Some(environment) = &query.environment {
+        if let Err(err) = state.settings.environment(environment) {
+            return Ok(Box::new(warp::reply::with_status(
+                err.to_string(),
+                StatusCode::NOT_FOUND,
+            )));
+        }
+    }
+
+    // We subscribe before reading the history, so that no change is lost in between.
+    let changes = state.changes.subscribe();
+    let history = match last_event_id {
+        Some(id) => {
+            match state
+                .db
+                .status_events(query.environment.as_deref(), id)
+                .await
+            {
+                Ok(history) => history,
+                Err(err) => {
+                    return Ok(Box::new(warp::reply::with_status(
+                        err.to_string(),
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )))
+                }
+            }
+        }
+        None => Vec::new(),
+    };
+
+    let last_id = history
+        .last()
+        .and_then(|change| change.id)
+        .or(last_event_id)
+        .unwrap_or(0);
+    let environment = query.environment;
+    let changes = changes.filter_map(move |change| {
+        let change = change.ok().filter(|change: &StatusChange| {
+            change.id.map_or(true, |id| id > last_id)
+                && environment
+                    .as_ref()
+                    .map_or(true, |environment| *environment == change.info.environment)
+        });
+        async move { change }
+    });
+
+    let events = stream::iter(history)
+        .chain(changes)
+        .map(|change| Ok::<_, Infallible>(event(change)));
+
+    Ok(Box::new(warp::sse::reply(
+        warp::sse::keep_alive().stream(events),
+    )))
+}
+
+// A change which could not be stored has no id, since it could not be replayed.
+fn event(change: StatusChange) -> impl ServerSentEvent {
+    match change.id {
+        Some(id) => (
+            warp::sse::id(id),
+            warp::sse::event("statusChanged"),
+            warp::sse::json(change.info),
+        )
+            .into_a(),
+        None => (
+            warp::sse::event("statusChanged"),
+            warp::sse::json(change.info),
+        )
+            .into_b(),
+    }
+}
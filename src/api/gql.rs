@@ -1,6 +1,16 @@
-use juniper::{EmptyMutation, EmptySubscription, FieldResult, IntoFieldError, RootNode};
+use chrono::{DateTime, Utc};
+use futures::stream::{self, Stream, StreamExt};
+use juniper::{FieldError, FieldResult, IntoFieldError, RootNode};
+use std::pin::Pin;
 
+use super::alerts;
+use super::availability;
+use super::history;
+use super::lifecycle;
 use super::model;
+use super::silences;
+use super::trends;
+use crate::poller;
 use crate::state;
 
 #[derive(Debug, Clone)]
@@ -16,21 +26,216 @@ pub struct Query;
     Context = Context
 )]
 impl Query {
-    /// Return a list of all features
-    async fn status(&self, context: &Context) -> FieldResult<model::BragiInfoResponseBody> {
-        let bragi_url = format!(
-            "http://{}:{}",
-            context.state.settings.bragi.host, context.state.settings.bragi.port
-        );
-        model::status(&bragi_url)
+    /// Return the list of environments watched by this service
+    fn environments(&self, context: &Context) -> Vec<model::EnvironmentInfo> {
+        context
+            .state
+            .settings
+            .environments
+            .iter()
+            .map(model::EnvironmentInfo::from)
+            .collect()
+    }
+
+    /// Return the last known status of bragi for the given environment, unless a refresh is
+    /// forced.
+    async fn status(
+        &self,
+        environment: String,
+        force_refresh: Option<bool>,
+        context: &Context,
+    ) -> FieldResult<model::BragiInfoResponseBody> {
+        let env = context
+            .state
+            .settings
+            .environment(&environment)
+            .map_err(IntoFieldError::into_field_error)?;
+        let info = poller::snapshot(&context.state, env, force_refresh.unwrap_or(false)).await;
+        Ok(model::BragiInfoResponseBody::from(info))
+    }
+
+    /// Return the last known status of bragi for all the environments, unless a refresh is
+    /// forced.
+    async fn all_statuses(
+        &self,
+        force_refresh: Option<bool>,
+        context: &Context,
+    ) -> model::MultBragiInfoResponseBody {
+        let infos = poller::snapshots(&context.state, force_refresh.unwrap_or(false)).await;
+        model::MultBragiInfoResponseBody::from(infos)
+    }
+
+    /// Return the statuses of bragi recorded for the given environment between 'from' (by
+    /// default, a day before 'to') and 'to' (by default, now)
+    async fn status_history(
+        &self,
+        environment: String,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        context: &Context,
+    ) -> FieldResult<history::StatusHistoryResponseBody> {
+        history::status_history(context, &environment, from, to)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Return the availability of bragi and its elasticsearch for the given environment over
+    /// a window (eg '30d', '24h', by default '30d') ending at 'to' (by default, now). Older probe
+    /// results are only known per hour or per day, prorated when the window starts or ends within
+    /// the hour or day.
+    async fn availability(
+        &self,
+        environment: String,
+        window: Option<String>,
+        to: Option<DateTime<Utc>>,
+        context: &Context,
+    ) -> FieldResult<availability::Availability> {
+        availability::availability(context, &environment, window, to)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Return the indices which appeared, disappeared, or were replaced in the given environment
+    /// (by default, all environments) between 'from' (by default, a week before 'to') and 'to' (by
+    /// default, now)
+    async fn index_events(
+        &self,
+        environment: Option<String>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        context: &Context,
+    ) -> FieldResult<lifecycle::IndexEventsResponseBody> {
+        lifecycle::index_events(context, environment, from, to)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Return the document counts of a coverage and place type in the given environment, across
+    /// probes and index generations, between 'from' (by default, a week before 'to') and 'to' (by
+    /// default, now)
+    async fn document_counts(
+        &self,
+        environment: String,
+        coverage: String,
+        place_type: String,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        context: &Context,
+    ) -> FieldResult<trends::DocumentCountsResponseBody> {
+        trends::document_counts(context, &environment, &coverage, &place_type, from, to)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Return the document count drops detected in the given environment (by default, all
+    /// environments) between 'from' (by default, a week before 'to') and 'to' (by default, now)
+    async fn document_count_drops(
+        &self,
+        environment: Option<String>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        context: &Context,
+    ) -> FieldResult<trends::CountDropsResponseBody> {
+        trends::document_count_drops(context, environment, from, to)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Return the alerts of the given environment (by default, all environments), optionally
+    /// only those in the given state, the most recent first
+    async fn alerts(
+        &self,
+        environment: Option<String>,
+        state: Option<alerts::AlertState>,
+        context: &Context,
+    ) -> FieldResult<alerts::AlertsResponseBody> {
+        alerts::alerts(context, environment, state)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Return the silences, the most recent first, or only those active now
+    async fn silences(
+        &self,
+        active: Option<bool>,
+        context: &Context,
+    ) -> FieldResult<silences::SilencesResponseBody> {
+        silences::silences(context, active)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+}
+
+pub struct Mutation;
+
+#[juniper::graphql_object(
+    Context = Context
+)]
+impl Mutation {
+    /// Suppress, from now on and for the given duration (eg '2h'), the notifications of the alerts
+    /// of the given environment, rule and coverage (by default, any environment, rule or coverage)
+    async fn silence(
+        &self,
+        environment: Option<String>,
+        rule: Option<String>,
+        coverage: Option<String>,
+        duration: String,
+        comment: Option<String>,
+        context: &Context,
+    ) -> FieldResult<silences::Silence> {
+        silences::silence(context, environment, rule, coverage, &duration, comment)
             .await
             .map_err(IntoFieldError::into_field_error)
-            .into()
+    }
+
+    /// End a silence now
+    async fn expire_silence(&self, id: i32, context: &Context) -> FieldResult<silences::Silence> {
+        silences::expire_silence(context, id)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Acknowledge a firing alert
+    async fn acknowledge(
+        &self,
+        rule: String,
+        environment: String,
+        comment: Option<String>,
+        context: &Context,
+    ) -> FieldResult<alerts::Alert> {
+        alerts::acknowledge(context, &rule, &environment, comment)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+}
+
+type BragiInfoStream = Pin<Box<dyn Stream<Item = Result<model::BragiInfo, FieldError>> + Send>>;
+
+pub struct Subscription;
+
+#[juniper::graphql_subscription(
+    Context = Context
+)]
+impl Subscription {
+    /// Return the status of bragi for the given environment whenever the availability of bragi or
+    /// its elasticsearch, its version, or its indices change
+    async fn status_changed(&self, environment: String, context: &Context) -> BragiInfoStream {
+        if let Err(err) = context.state.settings.environment(&environment) {
+            return Box::pin(stream::once(async move { Err(err.into_field_error()) }));
+        }
+        let changes = context.state.changes.subscribe().filter_map(move |change| {
+            let info = change
+                .ok()
+                .map(|change| change.info)
+                .filter(|info| info.environment == environment);
+            async move { info.map(Ok) }
+        });
+        Box::pin(changes)
     }
 }
 
-type Schema = RootNode<'static, Query, EmptyMutation<Context>, EmptySubscription<Context>>;
+pub type Schema = RootNode<'static, Query, Mutation, Subscription>;
 
 pub fn schema() -> Schema {
-    Schema::new(Query, EmptyMutation::new(), EmptySubscription::new())
+    Schema::new(Query, Mutation, Subscription)
 }
@@ -0,0 +1,114 @@
+use chrono::{Duration, Utc};
+use serde::Serialize;
+use std::convert::{Infallible, TryFrom};
+use warp::http::StatusCode;
+use warp::Reply;
+
+use crate::settings::Settings;
+use crate::state::State;
+
+/// The result of a single check.
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub ok: bool,
+    pub details: String,
+}
+
+impl Check {
+    fn new(name: &str, res: Result<String, String>) -> Self {
+        let (ok, details) = match res {
+            Ok(details) => (true, details),
+            Err(details) => (false, details),
+        };
+        Check {
+            name: String::from(name),
+            ok,
+            details,
+        }
+    }
+}
+
+/// The response body of the health endpoints.
+#[derive(Debug, Serialize)]
+pub struct HealthResponseBody {
+    /// 'ok', or 'failing' if any check failed.
+    pub status: String,
+    pub checks: Vec<Check>,
+}
+
+/// Tell whether the service is alive: the process answers, and the poller is running.
+pub async fn healthz(state: State) -> Result<Box<dyn Reply>, Infallible> {
+    let checks = vec![
+        Check::new("process", Ok(String::from("running"))),
+        Check::new("poller", poller_running(&state)),
+    ];
+    Ok(reply(checks))
+}
+
+/// Tell whether the service is ready to serve: the configuration is valid, the first probe cycle
+/// completed, and the database is reachable.
+pub async fn readyz(state: State) -> Result<Box<dyn Reply>, Infallible> {
+    let first_cycle = match state.heartbeat.completed() {
+        Some(completed) => Ok(format!(
+            "last cycle completed at {}",
+            completed.to_rfc3339()
+        )),
+        None => Err(String::from("the first probe cycle has not completed")),
+    };
+    let storage = state
+        .db
+        .ping()
+        .await
+        .map(|_| String::from("reachable"))
+        .map_err(|err| err.to_string());
+    let checks = vec![
+        Check::new("config", check_config(&state.settings)),
+        Check::new("first_probe_cycle", first_cycle),
+        Check::new("storage", storage),
+    ];
+    Ok(reply(checks))
+}
+
+// The poller is considered stuck, or dead, if no cycle started for three intervals.
+fn poller_running(state: &State) -> Result<String, String> {
+    let interval = i64::try_from(state.settings.poller.interval).unwrap_or(i64::MAX / 3);
+    let started = state
+        .heartbeat
+        .started()
+        .ok_or_else(|| String::from("the poller has not started"))?;
+    let elapsed = Utc::now() - started;
+    if elapsed > Duration::seconds(3 * interval) {
+        Err(format!(
+            "no probe cycle started for {}s (interval {}s)",
+            elapsed.num_seconds(),
+            interval
+        ))
+    } else {
+        Ok(format!("last cycle started {}s ago", elapsed.num_seconds()))
+    }
+}
+
+// The settings, including their durations and times of day, are validated when they are loaded,
+// so the service would not have started with an invalid configuration.
+fn check_config(settings: &Settings) -> Result<String, String> {
+    Ok(format!(
+        "{} environments, {} alert rules",
+        settings.environments.len(),
+        settings.alerts.len()
+    ))
+}
+
+fn reply(checks: Vec<Check>) -> Box<dyn Reply> {
+    let ok = checks.iter().all(|check| check.ok);
+    let body = HealthResponseBody {
+        status: String::from(if ok { "ok" } else { "failing" }),
+        checks,
+    };
+    let status = if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    Box::new(warp::reply::with_status(warp::reply::json(&body), status))
+}
@@ -0,0 +1,46 @@
+use chrono::{DateTime, Duration, Utc};
+use juniper::GraphQLObject;
+use serde::Serialize;
+
+use super::gql::Context;
+use super::model::BragiInfo;
+use crate::error;
+use crate::utils;
+
+/// The response body for the past statuses of an environment
+#[derive(Debug, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusHistoryResponseBody {
+    snapshots: Vec<BragiInfo>,
+    snapshots_count: i32,
+}
+
+impl From<Vec<BragiInfo>> for StatusHistoryResponseBody {
+    fn from(snapshots: Vec<BragiInfo>) -> Self {
+        let snapshots_count = utils::count(snapshots.len());
+        Self {
+            snapshots,
+            snapshots_count,
+        }
+    }
+}
+
+/// Retrieve the statuses of an environment recorded between 'from' (by default, a day before
+/// 'to') and 'to' (by default, now).
+pub async fn status_history(
+    context: &Context,
+    environment: &str,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+) -> Result<StatusHistoryResponseBody, error::Error> {
+    let env = context.state.settings.environment(environment)?;
+    let to = to.unwrap_or_else(Utc::now);
+    let from = match from {
+        Some(from) => from,
+        None => utils::time_before(to, Duration::days(1))?,
+    };
+
+    let snapshots = context.state.db.snapshots(&env.name, from, to).await?;
+
+    Ok(StatusHistoryResponseBody::from(snapshots))
+}
@@ -0,0 +1,294 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Utc};
+use std::convert::TryFrom;
+
+use super::model::PrivateStatus;
+use crate::error;
+use crate::settings;
+
+/// The information extracted from the name of an index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexName {
+    pub prefix: String,
+    pub place_type: String,
+    pub coverage: String,
+    pub private: PrivateStatus,
+    pub date: DateTime<Utc>,
+    /// The nanoseconds of the date, if the scheme has a nanos field.
+    pub nanos: Option<i32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Prefix,
+    PlaceType,
+    Coverage,
+    Date,
+    Time,
+    Nanos,
+    Any,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(String),
+    Field(Field),
+}
+
+/// A naming scheme, eg '{prefix}_{place_type}_{coverage}_{date}_{time}_{nanos}'
+/// Fields are enclosed in braces, and must be separated by some literal text. The available fields
+/// are prefix, place_type, coverage, date, time, nanos, and '_' which matches anything.
+#[derive(Debug, Clone)]
+pub struct Scheme {
+    tokens: Vec<Token>,
+}
+
+impl Scheme {
+    pub fn new(scheme: &str) -> Result<Self, error::Error> {
+        let invalid = |details: &str| error::Error::InvalidIndexScheme {
+            scheme: String::from(scheme),
+            details: String::from(details),
+        };
+
+        let mut tokens = Vec::new();
+        let mut rest = scheme;
+        while !rest.is_empty() {
+            if let Some(inner) = rest.strip_prefix('{') {
+                let end = inner.find('}').ok_or_else(|| invalid("unclosed brace"))?;
+                let field = match &inner[..end] {
+                    "prefix" => Field::Prefix,
+                    "place_type" => Field::PlaceType,
+                    "coverage" => Field::Coverage,
+                    "date" => Field::Date,
+                    "time" => Field::Time,
+                    "nanos" => Field::Nanos,
+                    "_" => Field::Any,
+                    _ => return Err(invalid("unknown field")),
+                };
+                if let Some(Token::Field(_)) = tokens.last() {
+                    return Err(invalid("fields must be separated by literal text"));
+                }
+                tokens.push(Token::Field(field));
+                rest = &inner[end + 1..];
+            } else {
+                let end = rest.find('{').unwrap_or(rest.len());
+                tokens.push(Token::Literal(String::from(&rest[..end])));
+                rest = &rest[end..];
+            }
+        }
+
+        for field in &[Field::PlaceType, Field::Coverage, Field::Date] {
+            if !tokens.contains(&Token::Field(*field)) {
+                return Err(invalid("place_type, coverage and date are mandatory"));
+            }
+        }
+
+        Ok(Scheme { tokens })
+    }
+
+    // Split the name into the values of the fields of the scheme. A field extends up to the first
+    // occurrence of the literal that follows it, or to the end of the name.
+    fn split<'a>(&self, name: &'a str) -> Option<Vec<(Field, &'a str)>> {
+        let mut values = Vec::new();
+        let mut rest = name;
+        let mut tokens = self.tokens.iter().peekable();
+        while let Some(token) = tokens.next() {
+            match token {
+                Token::Literal(literal) => {
+                    rest = rest.strip_prefix(literal.as_str())?;
+                }
+                Token::Field(field) => {
+                    let end = match tokens.peek() {
+                        Some(Token::Literal(literal)) => rest.find(literal.as_str())?,
+                        _ => rest.len(),
+                    };
+                    if end == 0 {
+                        return None;
+                    }
+                    values.push((*field, &rest[..end]));
+                    rest = &rest[end..];
+                }
+            }
+        }
+        if rest.is_empty() {
+            Some(values)
+        } else {
+            None
+        }
+    }
+}
+
+/// Parse index names following one of the configured naming schemes.
+#[derive(Debug, Clone)]
+pub struct IndexNaming {
+    schemes: Vec<Scheme>,
+    default_prefix: String,
+    date_format: String,
+    time_format: String,
+    private_marker: String,
+}
+
+impl IndexNaming {
+    pub fn new(settings: &settings::Indices) -> Result<Self, error::Error> {
+        let schemes = settings
+            .schemes
+            .iter()
+            .map(|scheme| Scheme::new(scheme))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(IndexNaming {
+            schemes,
+            default_prefix: settings.default_prefix.clone(),
+            date_format: settings.date_format.clone(),
+            time_format: settings.time_format.clone(),
+            private_marker: settings.private_marker.clone(),
+        })
+    }
+
+    /// The prefix used when bragi's elasticsearch url does not specify one.
+    pub fn default_prefix(&self) -> &str {
+        &self.default_prefix
+    }
+
+    /// Parse the name of an index, using the first scheme that matches. An index whose prefix is
+    /// not the expected one is not recognized.
+    pub fn parse(&self, name: &str, prefix: &str) -> Option<IndexName> {
+        self.schemes
+            .iter()
+            .find_map(|scheme| self.parse_with(scheme, name, prefix))
+    }
+
+    fn parse_with(&self, scheme: &Scheme, name: &str, prefix: &str) -> Option<IndexName> {
+        let mut index_prefix = String::from(prefix);
+        let mut place_type = None;
+        let mut coverage = None;
+        let mut date = None;
+        let mut time = NaiveTime::from_hms(0, 0, 0);
+        let mut nanos = None;
+
+        for (field, value) in scheme.split(name)? {
+            match field {
+                Field::Prefix => {
+                    if value != prefix {
+                        return None;
+                    }
+                    index_prefix = String::from(value);
+                }
+                Field::PlaceType => place_type = Some(value),
+                Field::Coverage => coverage = Some(value),
+                Field::Date => {
+                    date = Some(NaiveDate::parse_from_str(value, &self.date_format).ok()?)
+                }
+                Field::Time => time = NaiveTime::parse_from_str(value, &self.time_format).ok()?,
+                Field::Nanos => {
+                    if !value.chars().all(|c| c.is_ascii_digit()) {
+                        return None;
+                    }
+                    nanos = Some(value.parse::<i32>().ok().filter(|n| *n < 1_000_000_000)?);
+                }
+                Field::Any => {}
+            }
+        }
+
+        let coverage = coverage?;
+        let (private, coverage) = match coverage.strip_prefix(self.private_marker.as_str()) {
+            Some(coverage) if !self.private_marker.is_empty() => (PrivateStatus::Private, coverage),
+            _ => (PrivateStatus::Public, coverage),
+        };
+
+        Some(IndexName {
+            prefix: index_prefix,
+            place_type: String::from(place_type?),
+            coverage: String::from(coverage),
+            private,
+            date: DateTime::<Utc>::from_utc(
+                NaiveDateTime::new(
+                    date?,
+                    time.with_nanosecond(u32::try_from(nanos.unwrap_or(0)).ok()?)?,
+                ),
+                Utc,
+            ),
+            nanos,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn naming() -> IndexNaming {
+        IndexNaming::new(&settings::Indices {
+            default_prefix: String::from("munin"),
+            schemes: vec![
+                String::from("{prefix}_{place_type}_{coverage}_{date}_{time}_{nanos}"),
+                String::from("{prefix}_{place_type}_{coverage}_{date}_{time}"),
+            ],
+            date_format: String::from("%Y%m%d"),
+            time_format: String::from("%H%M%S"),
+            private_marker: String::from("priv."),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn should_parse_mimir_v2_names() {
+        let name = naming()
+            .parse("munin_addr_priv.fr_20200417_091503_837543218", "munin")
+            .unwrap();
+        assert_eq!(
+            name,
+            IndexName {
+                prefix: String::from("munin"),
+                place_type: String::from("addr"),
+                coverage: String::from("fr"),
+                private: PrivateStatus::Private,
+                date: Utc.ymd(2020, 4, 17).and_hms_nano(9, 15, 3, 837_543_218),
+                nanos: Some(837_543_218),
+            }
+        );
+    }
+
+    #[test]
+    fn should_parse_names_without_nanos() {
+        let name = naming()
+            .parse("munin_poi_fr_20200417_091503", "munin")
+            .unwrap();
+        assert_eq!(name.private, PrivateStatus::Public);
+        assert_eq!(name.date, Utc.ymd(2020, 4, 17).and_hms(9, 15, 3));
+        assert_eq!(name.nanos, None);
+    }
+
+    #[test]
+    fn should_not_recognize_kibana_and_aliases() {
+        let naming = naming();
+        assert_eq!(naming.parse(".kibana", "munin"), None);
+        assert_eq!(naming.parse(".kibana_1", "munin"), None);
+        assert_eq!(naming.parse("munin_addr_fr", "munin"), None);
+        assert_eq!(naming.parse("munin_addr", "munin"), None);
+        assert_eq!(naming.parse("munin", "munin"), None);
+    }
+
+    #[test]
+    fn should_not_recognize_other_prefixes() {
+        assert_eq!(
+            naming().parse("other_addr_fr_20200417_091503", "munin"),
+            None
+        );
+    }
+
+    #[test]
+    fn should_not_recognize_malformed_dates() {
+        let naming = naming();
+        assert_eq!(naming.parse("munin_addr_fr_20201341_091503", "munin"), None);
+        assert_eq!(naming.parse("munin_addr_fr_20200417_256103", "munin"), None);
+        assert_eq!(
+            naming.parse("munin_addr_fr_20200417_091503_12x", "munin"),
+            None
+        );
+        assert_eq!(
+            naming.parse("munin_addr_fr_20200417_091503_+1234", "munin"),
+            None
+        );
+    }
+}
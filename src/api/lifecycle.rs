@@ -0,0 +1,133 @@
+use chrono::{DateTime, Duration, Utc};
+use juniper::{GraphQLEnum, GraphQLObject};
+use serde::{Deserialize, Serialize};
+
+use super::gql::Context;
+use super::model::{ElasticsearchIndexInfo, PrivateStatus};
+use crate::error;
+use crate::utils;
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Copy, GraphQLEnum)]
+#[serde(rename_all = "camelCase")]
+pub enum IndexEventKind {
+    /// A new index is available
+    Appeared,
+    /// An index was deleted, and no newer index is available for its coverage and place type
+    Disappeared,
+    /// An index was deleted, and a newer index is available for its coverage and place type
+    Replaced,
+}
+
+/// A transition in the indices of an environment.
+#[derive(Debug, Deserialize, Serialize, Clone, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexEvent {
+    pub environment: String,
+    pub kind: IndexEventKind,
+    pub place_type: String,
+    pub coverage: String,
+    pub private: PrivateStatus,
+    /// The index which appeared or disappeared, or the index replacing the previous one.
+    pub index: String,
+    /// The index which was replaced.
+    pub previous_index: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// The response body for index events
+#[derive(Debug, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexEventsResponseBody {
+    events: Vec<IndexEvent>,
+    events_count: i32,
+}
+
+impl From<Vec<IndexEvent>> for IndexEventsResponseBody {
+    fn from(events: Vec<IndexEvent>) -> Self {
+        let events_count = utils::count(events.len());
+        Self {
+            events,
+            events_count,
+        }
+    }
+}
+
+// Two indices with the same key hold the same data, possibly at different dates.
+fn same_key(a: &ElasticsearchIndexInfo, b: &ElasticsearchIndexInfo) -> bool {
+    a.place_type == b.place_type && a.coverage == b.coverage && a.private == b.private
+}
+
+/// Compare two successive lists of indices of an environment, and return the transitions. A new
+/// index replacing a deleted one is only reported as a replacement.
+pub fn diff(
+    environment: &str,
+    previous: &[ElasticsearchIndexInfo],
+    current: &[ElasticsearchIndexInfo],
+    occurred_at: DateTime<Utc>,
+) -> Vec<IndexEvent> {
+    let event = |kind: IndexEventKind,
+                 index: &ElasticsearchIndexInfo,
+                 previous_index: Option<String>| IndexEvent {
+        environment: String::from(environment),
+        kind,
+        place_type: index.place_type.clone(),
+        coverage: index.coverage.clone(),
+        private: index.private.clone(),
+        index: index.label.clone(),
+        previous_index,
+        occurred_at,
+    };
+
+    let disappeared = previous
+        .iter()
+        .filter(|index| !current.iter().any(|c| c.label == index.label))
+        .map(|index| {
+            let newest = current
+                .iter()
+                .filter(|c| same_key(c, index) && c.date > index.date)
+                .max_by_key(|c| c.date);
+            match newest {
+                Some(newest) => event(IndexEventKind::Replaced, newest, Some(index.label.clone())),
+                None => event(IndexEventKind::Disappeared, index, None),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let appeared = current
+        .iter()
+        .filter(|index| !previous.iter().any(|p| p.label == index.label))
+        .filter(|index| {
+            !disappeared
+                .iter()
+                .any(|e| e.kind == IndexEventKind::Replaced && e.index == index.label)
+        })
+        .map(|index| event(IndexEventKind::Appeared, index, None));
+
+    appeared.chain(disappeared.iter().cloned()).collect()
+}
+
+/// Retrieve the index events of an environment (or all environments) between 'from' (by default,
+/// a week before 'to') and 'to' (by default, now).
+pub async fn index_events(
+    context: &Context,
+    environment: Option<String>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+) -> Result<IndexEventsResponseBody, error::Error> {
+    if let Some(environment) = &environment {
+        context.state.settings.environment(environment)?;
+    }
+    let to = to.unwrap_or_else(Utc::now);
+    let from = match from {
+        Some(from) => from,
+        None => utils::time_before(to, Duration::weeks(1))?,
+    };
+
+    let events = context
+        .state
+        .db
+        .index_events(environment.as_deref(), from, to)
+        .await?;
+
+    Ok(IndexEventsResponseBody::from(events))
+}
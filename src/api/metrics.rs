@@ -0,0 +1,288 @@
+use chrono::{DateTime, Utc};
+use prometheus::proto::MetricFamily;
+use prometheus::{
+    Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use snafu::ResultExt;
+use std::convert::Infallible;
+use std::fmt;
+use std::time::Duration;
+use warp::http::StatusCode;
+use warp::Reply;
+
+use super::model::{
+    self, BragiInfo, BragiStatus, ElasticsearchIndexInfo, PrivateStatus, ProbeStage,
+};
+use crate::error;
+use crate::poller;
+use crate::state::State;
+
+/// The metrics of bragi-status itself, kept for the lifetime of the service.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    probe_duration: HistogramVec,
+    probe_failures: IntCounterVec,
+    graphql_requests: IntCounterVec,
+    graphql_duration: HistogramVec,
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metrics").finish()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self, error::Error> {
+        let registry = Registry::new();
+        let probe_duration = histogram(
+            &registry,
+            "bragi_status_probe_duration_seconds",
+            "The latency of the HTTP calls of each stage of the probes",
+            &["environment", "stage"],
+        )?;
+        let probe_failures = counter(
+            &registry,
+            "bragi_status_probe_failures_total",
+            "The number of failed stages of the probes, by kind of error",
+            &["environment", "stage", "error"],
+        )?;
+        let graphql_requests = counter(
+            &registry,
+            "bragi_status_graphql_requests_total",
+            "The number of GraphQL requests, by HTTP status",
+            &["status"],
+        )?;
+        let graphql_duration = histogram(
+            &registry,
+            "bragi_status_graphql_request_duration_seconds",
+            "The time taken to answer GraphQL requests",
+            &["status"],
+        )?;
+
+        Ok(Self {
+            registry,
+            probe_duration,
+            probe_failures,
+            graphql_requests,
+            graphql_duration,
+        })
+    }
+
+    /// Record the latency of the HTTP calls, and the failures, of a probe.
+    pub fn observe_probe(&self, info: &BragiInfo) {
+        let env = info.environment.as_str();
+        for probe in &info.probes {
+            self.probe_duration
+                .with_label_values(&[env, stage_label(probe.stage)])
+                .observe(probe.latency_ms / 1000.0);
+        }
+        for err in &info.errors {
+            self.probe_failures
+                .with_label_values(&[env, stage_label(err.stage), err.kind.as_str()])
+                .inc();
+        }
+    }
+
+    /// Record a GraphQL request, answered with the given HTTP status.
+    pub fn observe_graphql(&self, status: u16, elapsed: Duration) {
+        let status = status.to_string();
+        self.graphql_requests
+            .with_label_values(&[status.as_str()])
+            .inc();
+        self.graphql_duration
+            .with_label_values(&[status.as_str()])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    fn gather(&self) -> Vec<MetricFamily> {
+        self.registry.gather()
+    }
+}
+
+/// Serve the last known status of all the environments, and the metrics of bragi-status itself,
+/// in the Prometheus text format.
+pub async fn metrics(state: State) -> Result<Box<dyn Reply>, Infallible> {
+    let infos = poller::snapshots(&state, false).await;
+    match encode(&infos, &state.metrics, Utc::now()) {
+        Ok(body) => Ok(Box::new(warp::reply::with_header(
+            body,
+            "content-type",
+            prometheus::TEXT_FORMAT,
+        ))),
+        Err(err) => Ok(Box::new(warp::reply::with_status(
+            err.to_string(),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ))),
+    }
+}
+
+/// Encode the status of the environments as gauges, followed by the metrics of bragi-status. The
+/// registry of the gauges is built for each scrape, so that the indices which disappeared are not
+/// exported anymore.
+pub fn encode(
+    infos: &[BragiInfo],
+    metrics: &Metrics,
+    now: DateTime<Utc>,
+) -> Result<String, error::Error> {
+    let registry = Registry::new();
+    let up = gauge(
+        &registry,
+        "bragi_up",
+        "Whether bragi answered the last probe",
+        &["environment"],
+    )?;
+    let elasticsearch_up = gauge(
+        &registry,
+        "bragi_elasticsearch_up",
+        "Whether the elasticsearch of bragi answered the last probe",
+        &["environment"],
+    )?;
+    let documents = gauge(
+        &registry,
+        "bragi_index_documents",
+        "The number of documents of the newest index of each coverage and place type",
+        &["environment", "coverage", "place_type", "private"],
+    )?;
+    let age = gauge(
+        &registry,
+        "bragi_index_age_seconds",
+        "The age of the newest index of each coverage and place type",
+        &["environment", "coverage", "place_type", "private"],
+    )?;
+    let version = gauge(
+        &registry,
+        "bragi_version_info",
+        "The version of bragi, as a label",
+        &["environment", "version"],
+    )?;
+
+    for info in infos {
+        let env = info.environment.as_str();
+        up.with_label_values(&[env])
+            .set(flag(info.status != BragiStatus::BragiNotAvailable));
+        elasticsearch_up
+            .with_label_values(&[env])
+            .set(flag(info.status == BragiStatus::Available));
+        if !info.version.is_empty() {
+            version
+                .with_label_values(&[env, info.version.as_str()])
+                .set(1.0);
+        }
+        for index in newest_indices(info.indices().unwrap_or_default()) {
+            let private = if index.private == PrivateStatus::Private {
+                "true"
+            } else {
+                "false"
+            };
+            let labels = [
+                env,
+                index.coverage.as_str(),
+                index.place_type.as_str(),
+                private,
+            ];
+            if let Some(count) = index.count {
+                documents.with_label_values(&labels).set(f64::from(count));
+            }
+            age.with_label_values(&labels)
+                .set((now - index.date).num_seconds() as f64);
+        }
+    }
+
+    let mut families = registry.gather();
+    families.extend(metrics.gather());
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&families, &mut buffer)
+        .context(error::PrometheusError {
+            details: String::from("Could not encode metrics"),
+        })?;
+    Ok(String::from_utf8_lossy(&buffer).into_owned())
+}
+
+fn gauge(
+    registry: &Registry,
+    name: &str,
+    help: &str,
+    labels: &[&str],
+) -> Result<GaugeVec, error::Error> {
+    let gauge = GaugeVec::new(Opts::new(name, help), labels).context(error::PrometheusError {
+        details: format!("Could not create metric {}", name),
+    })?;
+    registry
+        .register(Box::new(gauge.clone()))
+        .context(error::PrometheusError {
+            details: format!("Could not register metric {}", name),
+        })?;
+    Ok(gauge)
+}
+
+fn counter(
+    registry: &Registry,
+    name: &str,
+    help: &str,
+    labels: &[&str],
+) -> Result<IntCounterVec, error::Error> {
+    let counter =
+        IntCounterVec::new(Opts::new(name, help), labels).context(error::PrometheusError {
+            details: format!("Could not create metric {}", name),
+        })?;
+    registry
+        .register(Box::new(counter.clone()))
+        .context(error::PrometheusError {
+            details: format!("Could not register metric {}", name),
+        })?;
+    Ok(counter)
+}
+
+fn histogram(
+    registry: &Registry,
+    name: &str,
+    help: &str,
+    labels: &[&str],
+) -> Result<HistogramVec, error::Error> {
+    let histogram = HistogramVec::new(HistogramOpts::new(name, help), labels).context(
+        error::PrometheusError {
+            details: format!("Could not create metric {}", name),
+        },
+    )?;
+    registry
+        .register(Box::new(histogram.clone()))
+        .context(error::PrometheusError {
+            details: format!("Could not register metric {}", name),
+        })?;
+    Ok(histogram)
+}
+
+fn stage_label(stage: ProbeStage) -> &'static str {
+    match stage {
+        ProbeStage::Bragi => "bragi",
+        ProbeStage::BragiStatus => "bragi_status",
+        ProbeStage::Elasticsearch => "elasticsearch",
+        ProbeStage::ElasticsearchIndices => "elasticsearch_indices",
+    }
+}
+
+fn flag(value: bool) -> f64 {
+    if value {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+// The newest index of each coverage, place type and private status, which are the labels of the
+// index metrics.
+fn newest_indices(indices: &[ElasticsearchIndexInfo]) -> Vec<&ElasticsearchIndexInfo> {
+    model::newest_indices_by(indices, |index| {
+        (
+            index.coverage.as_str(),
+            index.place_type.as_str(),
+            index.private == PrivateStatus::Private,
+        )
+    })
+    .values()
+    .copied()
+    .collect()
+}
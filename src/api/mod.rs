@@ -6,3 +6,36 @@ pub mod model;
 pub mod utils;
 
 pub mod gql;
+
+/// History of the probe results
+pub mod history;
+
+/// Availability computed from the history
+pub mod availability;
+
+/// Indices appearing, disappearing and being replaced
+pub mod lifecycle;
+
+/// Document counts across probes and index generations
+pub mod trends;
+
+/// Server-sent events of status changes
+pub mod events;
+
+/// Alert rules evaluation
+pub mod alerts;
+
+/// Silences and maintenance windows
+pub mod silences;
+
+/// Prometheus metrics
+pub mod metrics;
+
+/// Plain JSON endpoints
+pub mod rest;
+
+/// Liveness and readiness of the service
+pub mod health;
+
+/// Parsing of elasticsearch index names
+pub mod index;
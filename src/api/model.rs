@@ -1,28 +1,68 @@
 // use chrono::prelude::*;
-use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
-use futures::future::TryFutureExt;
+use chrono::{DateTime, Utc};
+use futures::future;
 use juniper::{GraphQLEnum, GraphQLObject};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use snafu::ResultExt;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
 use url::Url;
 
+use super::index::IndexNaming;
 use crate::error;
+use crate::settings::Environment;
+use crate::utils;
 
 #[derive(Debug, Serialize, Deserialize, GraphQLObject)]
 pub struct BragiInfoResponseBody {
-    info: BragiInfo,
+    pub info: BragiInfo,
+    /// The age of the information, in seconds
+    pub age: f64,
 }
 
 impl From<BragiInfo> for BragiInfoResponseBody {
     fn from(info: BragiInfo) -> Self {
-        Self { info }
+        let age = (Utc::now() - info.updated_at).num_milliseconds() as f64 / 1000.0;
+        Self { info, age }
     }
 }
 
-#[derive(Debug, Deserialize)]
-struct Env {
-    name: String,
-    url: String,
+/// The response body for the status of multiple environments
+#[derive(Debug, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct MultBragiInfoResponseBody {
+    infos: Vec<BragiInfoResponseBody>,
+    infos_count: i32,
+}
+
+impl From<Vec<BragiInfo>> for MultBragiInfoResponseBody {
+    fn from(infos: Vec<BragiInfo>) -> Self {
+        let infos = infos
+            .into_iter()
+            .map(BragiInfoResponseBody::from)
+            .collect::<Vec<_>>();
+        let infos_count = utils::count(infos.len());
+        Self { infos, infos_count }
+    }
+}
+
+/// A bragi environment, as declared in the configuration.
+#[derive(Debug, Serialize, GraphQLObject)]
+pub struct EnvironmentInfo {
+    pub name: String,
+    pub url: String,
+    pub labels: Vec<String>,
+}
+
+impl From<&Environment> for EnvironmentInfo {
+    fn from(env: &Environment) -> Self {
+        Self {
+            name: env.name.clone(),
+            url: env.url.clone(),
+            labels: env.labels.clone(),
+        }
+    }
 }
 
 // This is used for POIs, to indicate if its a private or public source of POI.
@@ -33,6 +73,12 @@ pub enum PrivateStatus {
     Public,
 }
 
+impl Default for PrivateStatus {
+    fn default() -> Self {
+        PrivateStatus::Public
+    }
+}
+
 pub fn is_public(status: &PrivateStatus) -> bool {
     status == &PrivateStatus::Public
 }
@@ -52,14 +98,84 @@ pub enum BragiStatus {
     ElasticsearchNotAvailable,
 }
 
-#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+// The stages of the probe pipeline, in the order in which they are executed.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Copy, GraphQLEnum)]
+#[serde(rename_all = "camelCase")]
+pub enum ProbeStage {
+    Bragi,
+    BragiStatus,
+    Elasticsearch,
+    ElasticsearchIndices,
+}
+
+/// The reason why a stage of the probe pipeline failed.
+#[derive(Debug, Deserialize, Serialize, Clone, GraphQLObject)]
+pub struct StageError {
+    pub stage: ProbeStage,
+    /// The kind of error, eg 'NotAccessible'
+    #[serde(default)]
+    pub kind: String,
+    pub details: String,
+}
+
+/// A single HTTP call made by the probe pipeline.
+#[derive(Debug, Deserialize, Serialize, Clone, GraphQLObject)]
+pub struct Probe {
+    pub stage: ProbeStage,
+    pub url: String,
+    pub http_status: Option<i32>,
+    pub latency_ms: f64,
+    pub bytes: i32,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, GraphQLObject)]
 pub struct BragiInfo {
+    pub environment: String,
     pub label: String,
     pub url: String,
     pub version: String,
     pub status: BragiStatus,
     pub updated_at: DateTime<Utc>,
     pub elastic: Option<ElasticsearchInfo>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<StageError>,
+    #[serde(default)]
+    pub probes: Vec<Probe>,
+}
+
+impl BragiInfo {
+    /// Describe an environment for which nothing is known yet.
+    pub fn new(env: &Environment) -> Self {
+        BragiInfo {
+            environment: env.name.clone(),
+            label: format!("bragi_{}", env.name),
+            url: env.url.clone(),
+            version: String::from(""),
+            status: BragiStatus::BragiNotAvailable,
+            updated_at: Utc::now(),
+            elastic: None,
+            errors: Vec::new(),
+            probes: Vec::new(),
+        }
+    }
+
+    /// The indices of elasticsearch, if they could be retrieved.
+    pub fn indices(&self) -> Option<&[ElasticsearchIndexInfo]> {
+        match (&self.status, &self.elastic) {
+            (BragiStatus::Available, Some(elastic)) => Some(&elastic.indices),
+            _ => None,
+        }
+    }
+
+    // Record the failure of a stage of the probe pipeline.
+    fn fail(&mut self, stage: ProbeStage, err: error::Error) {
+        self.errors.push(StageError {
+            stage,
+            kind: String::from(err.kind()),
+            details: format!("{}", err),
+        });
+    }
 }
 
 // This struct is used to return the call to 'bragi/status'
@@ -80,6 +196,9 @@ pub struct ElasticsearchInfo {
     pub status: ServerStatus,
     pub version: String,
     pub indices: Vec<ElasticsearchIndexInfo>,
+    // The indices whose name does not follow any of the naming schemes.
+    #[serde(default)]
+    pub unrecognized_indices: Vec<String>,
     pub index_prefix: String, // eg munin
     pub updated_at: DateTime<Utc>,
 }
@@ -116,13 +235,37 @@ pub struct ElasticsearchIndexInfo {
     pub label: String,
     pub place_type: String,
     pub coverage: String,
-    #[serde(skip_serializing_if = "is_public")]
+    #[serde(default, skip_serializing_if = "is_public")]
     pub private: PrivateStatus,
     pub date: DateTime<Utc>,
-    pub count: i32,
+    /// The number of documents, unless elasticsearch did not report it (eg for a closed index).
+    #[serde(default)]
+    pub count: Option<i32>,
+    /// The nanoseconds of the date, if the name of the index includes them.
+    #[serde(default)]
+    pub nanos: Option<i32>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// The newest index for each key, the key being eg the coverage and place type of the index.
+pub fn newest_indices_by<'a, K, F>(
+    indices: impl IntoIterator<Item = &'a ElasticsearchIndexInfo>,
+    key: F,
+) -> BTreeMap<K, &'a ElasticsearchIndexInfo>
+where
+    K: Ord,
+    F: Fn(&'a ElasticsearchIndexInfo) -> K,
+{
+    let mut newest: BTreeMap<K, &ElasticsearchIndexInfo> = BTreeMap::new();
+    for index in indices {
+        let entry = newest.entry(key(index)).or_insert(index);
+        if index.date > entry.date {
+            *entry = index;
+        }
+    }
+    newest
+}
+
 #[derive(Debug, Deserialize, Clone, GraphQLObject)]
 pub struct ElasticsearchIndexInfoDetails {
     pub health: String,
@@ -132,8 +275,9 @@ pub struct ElasticsearchIndexInfoDetails {
     pub prim: i32,
     #[serde(skip)]
     pub rep: i32,
-    #[serde(rename = "docs.count")]
-    pub count: String,
+    // The count is not available for closed indices.
+    #[serde(rename = "docs.count", default)]
+    pub count: Option<String>,
     #[serde(rename = "docs.deleted", skip)]
     pub deleted: String,
     #[serde(rename = "store.size", skip)]
@@ -142,189 +286,259 @@ pub struct ElasticsearchIndexInfoDetails {
     pub pri_size: String,
 }
 
-pub async fn status(url: &str) -> Result<BragiInfoResponseBody, error::Error> {
-    let bragi_info = check_accessible(&url)
-        .and_then(check_bragi_status)
-        .and_then(check_elasticsearch_info)
-        .and_then(check_elasticsearch_indices)
-        .await?;
-
-    Ok(BragiInfoResponseBody::from(bragi_info))
-}
-
-// Return a pair (environment, url)
-// async fn get_url(env: &str) -> Result<(String, String), Error> {
-//     let foo = std::fs::read_to_string("env.json").context(Config {
-//         env: String::from("env.json"),
-//     })?;
-//
-//     let foos: Vec<Env> = serde_json::from_str(&foo).context(DeserializeError)?;
-//
-//     let info: HashMap<String, String> = foos
-//         .into_iter()
-//         .map(|env| (env.name, env.url))
-//         .collect::<HashMap<String, String>>();
-//
-//     info.get(env)
-//         .ok_or(Error::Environment {
-//             env: String::from(env),
-//         })
-//         .map(|s| (String::from(env), s.clone()))
-// }
+/// Probe the bragi of the given environment, and its elasticsearch backend.
+/// The returned BragiInfo describes how far the probe went: Failing stages are reported in its
+/// errors, and its status tells which of bragi or elasticsearch is not available.
+pub async fn status(
+    client: &reqwest::Client,
+    env: &Environment,
+    naming: &IndexNaming,
+) -> BragiInfo {
+    let mut info = BragiInfo::new(env);
 
-// Check that the url is accessible (should be done with some kind of 'ping')
-// and return its arguments
-async fn check_accessible(url: &str) -> Result<String, error::Error> {
-    let status = reqwest::get(url)
-        .await
-        .context(error::NotAccessible { url: url.clone() })?
-        .status();
+    if let Err(err) = check_accessible(client, &env.url, &mut info.probes).await {
+        info.fail(ProbeStage::Bragi, err);
+        return info;
+    }
 
-    if status.is_client_error() || status.is_server_error() {
-        Err(error::Error::MiscError {
-            details: format!("Could not reach url {}", &url),
+    let mut elastic = match check_bragi_status(client, env, naming, &mut info.probes).await {
+        Ok((version, elastic)) => {
+            info.version = version;
+            info.status = BragiStatus::ElasticsearchNotAvailable;
+            elastic
+        }
+        Err(err) => {
+            info.fail(ProbeStage::BragiStatus, err);
+            return info;
+        }
+    };
+
+    match check_elasticsearch_info(client, &elastic.url, &mut info.probes).await {
+        Ok(details) => {
+            elastic.name = details.name;
+            elastic.version = details.version.number;
+            elastic.status = ServerStatus::Available;
+            // The indices are only retrieved if elasticsearch answered.
+            let indices = check_elasticsearch_indices(
+                client,
+                &elastic.url,
+                &elastic.index_prefix,
+                naming,
+                &mut info.probes,
+            )
+            .await;
+            match indices {
+                Ok((indices, unrecognized_indices)) => {
+                    elastic.indices = indices;
+                    elastic.unrecognized_indices = unrecognized_indices;
+                    info.status = BragiStatus::Available;
+                }
+                Err(err) => info.fail(ProbeStage::ElasticsearchIndices, err),
+            }
+        }
+        Err(err) => info.fail(ProbeStage::Elasticsearch, err),
+    }
+
+    elastic.updated_at = Utc::now();
+    info.elastic = Some(elastic);
+    info.updated_at = Utc::now();
+    info
+}
+
+/// Retrieve the status of all the given environments concurrently.
+/// Each environment is probed independently, so that a failing environment does not prevent the
+/// others from being reported.
+pub async fn statuses(
+    client: &reqwest::Client,
+    envs: &[Environment],
+    naming: &IndexNaming,
+) -> Vec<BragiInfo> {
+    future::join_all(envs.iter().map(|env| status(client, env, naming))).await
+}
+
+/// Build the HTTP client of the probes, whose calls time out after the given number of seconds,
+/// so that an unresponsive environment does not stall the probes.
+pub fn client(timeout: u64) -> Result<reqwest::Client, error::Error> {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(timeout))
+        .build()
+        .context(error::ReqwestError {
+            details: String::from("Could not build HTTP client"),
         })
-    } else {
-        Ok(String::from(url))
+}
+
+// Perform a GET request on the url, and record the call in the probes.
+async fn fetch(
+    client: &reqwest::Client,
+    stage: ProbeStage,
+    url: &str,
+    probes: &mut Vec<Probe>,
+) -> Result<Vec<u8>, error::Error> {
+    let start = Instant::now();
+    let mut probe = Probe {
+        stage,
+        url: String::from(url),
+        http_status: None,
+        latency_ms: 0.0,
+        bytes: 0,
+        error: None,
+    };
+
+    let res: Result<Vec<u8>, error::Error> = async {
+        let response = client
+            .get(url)
+            .send()
+            .await
+            .context(error::NotAccessible { url })?;
+        let status = response.status();
+        probe.http_status = Some(i32::from(status.as_u16()));
+        let body = response.bytes().await.context(error::NotReadable { url })?;
+        probe.bytes = utils::count(body.len());
+        if status.is_client_error() || status.is_server_error() {
+            Err(error::Error::UnexpectedStatus {
+                url: String::from(url),
+                status: status.as_u16(),
+            })
+        } else {
+            Ok(body.to_vec())
+        }
     }
+    .await;
+
+    probe.latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+    if let Err(err) = &res {
+        probe.error = Some(format!("{}", err));
+    }
+    probes.push(probe);
+    res
 }
 
-async fn check_bragi_status(url: String) -> Result<BragiInfo, error::Error> {
-    println!("checking {} bragi status", url);
-    let status_url = format!("{}/status", url);
-    let status: BragiStatusDetails = reqwest::get(&status_url)
-        .await
-        .context(error::NotAccessible { url: url.clone() })?
-        .json()
+// Perform a GET request on the url, and deserialize the JSON response.
+async fn fetch_json<T: DeserializeOwned>(
+    client: &reqwest::Client,
+    stage: ProbeStage,
+    url: &str,
+    probes: &mut Vec<Probe>,
+) -> Result<T, error::Error> {
+    let body = fetch(client, stage, url, probes).await?;
+    serde_json::from_slice(&body)
+        .context(error::NotDeserializable { url })
+        .map_err(|err| {
+            if let Some(probe) = probes.last_mut() {
+                probe.error = Some(format!("{}", err));
+            }
+            err
+        })
+}
+
+// Check that the url is accessible (should be done with some kind of 'ping')
+async fn check_accessible(
+    client: &reqwest::Client,
+    url: &str,
+    probes: &mut Vec<Probe>,
+) -> Result<(), error::Error> {
+    fetch(client, ProbeStage::Bragi, url, probes)
         .await
-        .context(error::NotReadable { url: url.clone() })?;
+        .map(|_| ())
+}
+
+// Retrieve bragi's version, and the elasticsearch it uses.
+async fn check_bragi_status(
+    client: &reqwest::Client,
+    env: &Environment,
+    naming: &IndexNaming,
+    probes: &mut Vec<Probe>,
+) -> Result<(String, ElasticsearchInfo), error::Error> {
+    let status_url = format!("{}/status", env.url);
+    let status: BragiStatusDetails =
+        fetch_json(client, ProbeStage::BragiStatus, &status_url, probes).await?;
 
     // We brake the URL into its components, in order to get
     // the elastic search url, which may or may not include a port number
     // the name of the index, which is the first element in the path if it is present. If its not
-    // present, we assign a sensible value by default. This could be improved.
+    // present, we use the default prefix from the configuration.
     let elastic =
         Url::parse(&status.elasticsearch).context(error::ElasticsearchURLNotReadable {
-            url: String::from(status.elasticsearch),
+            url: String::from(&status.elasticsearch),
         })?;
 
+    let host = elastic.host_str().ok_or_else(|| error::Error::MiscError {
+        details: format!("No host in elasticsearch url {}", status.elasticsearch),
+    })?;
+
     let elastic_url = match elastic.port() {
-        None => format!("{}://{}", elastic.scheme(), elastic.host_str().unwrap()),
-        Some(port) => format!(
-            "{}://{}:{}",
-            elastic.scheme(),
-            elastic.host_str().unwrap(),
-            port
-        ),
+        None => format!("{}://{}", elastic.scheme(), host),
+        Some(port) => format!("{}://{}:{}", elastic.scheme(), host, port),
     };
 
-    // FIXME Hardcode munin
-    let prefix = String::from(elastic.path_segments().unwrap().next().unwrap_or("munin"));
+    let prefix = elastic
+        .path_segments()
+        .and_then(|mut segments| segments.next())
+        .filter(|segment| !segment.is_empty())
+        .unwrap_or_else(|| naming.default_prefix());
 
-    // We return a bragi info with empty elastic search indices... We delegate filling
+    // We return an elasticsearch info with empty elastic search indices... We delegate filling
     // this information to a later stage.
-    Ok(BragiInfo {
-        label: String::from("bragi"),
-        url,
-        version: status.version,
-        status: BragiStatus::Available,
-        elastic: Some(ElasticsearchInfo {
-            label: String::from("elasticsearch"),
+    Ok((
+        status.version,
+        ElasticsearchInfo {
+            label: format!("elasticsearch_{}", env.name),
             url: elastic_url,
             name: String::from(""),
             status: ServerStatus::NotAvailable,
             version: String::from(""),
             indices: Vec::new(),
-            index_prefix: prefix,
+            unrecognized_indices: Vec::new(),
+            index_prefix: String::from(prefix),
             updated_at: Utc::now(),
-        }),
-        updated_at: Utc::now(),
-    })
+        },
+    ))
 }
 
-async fn check_elasticsearch_info(info: BragiInfo) -> Result<BragiInfo, error::Error> {
-    let es_info = info.elastic.clone().ok_or(error::Error::MiscError {
-        details: String::from("hello"),
-    })?;
-    let details: ElasticsearhInfoDetails = reqwest::get(&es_info.url)
-        .await
-        .context(error::NotAccessible {
-            url: String::from(&es_info.url),
-        })?
-        .json()
-        .await
-        .context(error::NotAccessible {
-            url: String::from(&es_info.url),
-        })?;
-
-    // TODO: We're not extracting much information now,
-    // we need to get more...
-    let es_update_info = ElasticsearchInfo {
-        version: details.version.number,
-        ..es_info
-    };
-    Ok(BragiInfo {
-        elastic: Some(es_update_info),
-        ..info
-    })
+async fn check_elasticsearch_info(
+    client: &reqwest::Client,
+    url: &str,
+    probes: &mut Vec<Probe>,
+) -> Result<ElasticsearhInfoDetails, error::Error> {
+    fetch_json(client, ProbeStage::Elasticsearch, url, probes).await
 }
-//
+
 // We retrieve all indices in json format, then use serde to deserialize into a data structure,
-// and finally parse the label to extract the information.
-async fn check_elasticsearch_indices(info: BragiInfo) -> Result<BragiInfo, error::Error> {
-    let es_info = info.elastic.clone().ok_or(error::Error::MiscError {
-        details: String::from("hello"),
-    })?;
-    let indices_url = format!("{}/_cat/indices?format=json", es_info.url);
-    let indices: Vec<ElasticsearchIndexInfoDetails> = reqwest::get(&indices_url)
-        .await
-        .context(error::NotAccessible {
-            url: String::from(&es_info.url),
-        })?
-        .json()
-        .await
-        .context(error::NotAccessible {
-            url: String::from(&es_info.url),
-        })?;
+// and finally parse the label to extract the information. We return the indices which could be
+// parsed, and the names of those which could not.
+async fn check_elasticsearch_indices(
+    client: &reqwest::Client,
+    url: &str,
+    prefix: &str,
+    naming: &IndexNaming,
+    probes: &mut Vec<Probe>,
+) -> Result<(Vec<ElasticsearchIndexInfo>, Vec<String>), error::Error> {
+    let indices_url = format!("{}/_cat/indices?format=json", url);
+    let indices: Vec<ElasticsearchIndexInfoDetails> = fetch_json(
+        client,
+        ProbeStage::ElasticsearchIndices,
+        &indices_url,
+        probes,
+    )
+    .await?;
 
-    let indices = indices
-        .iter()
-        .map(|i| {
-            let zs: Vec<&str> = i.index.split('_').collect();
-            let (private, coverage) = if zs[2].starts_with("priv.") {
-                (PrivateStatus::Private, zs[2].chars().skip(5).collect())
-            } else {
-                (PrivateStatus::Public, zs[2].to_string())
-            };
-            ElasticsearchIndexInfo {
-                label: i.index.clone(),
-                place_type: zs[1].to_string(),
-                coverage,
-                private,
-                date: DateTime::<Utc>::from_utc(
-                    NaiveDateTime::new(
-                        NaiveDate::parse_from_str(zs[3], "%Y%m%d")
-                            .unwrap_or(NaiveDate::from_ymd(1970, 1, 1)),
-                        NaiveTime::parse_from_str(zs[4], "%H%M%S")
-                            .unwrap_or(NaiveTime::from_hms(0, 1, 1)),
-                    ),
-                    Utc,
-                ),
-                count: i.count.parse().unwrap_or(0),
+    let mut recognized = Vec::new();
+    let mut unrecognized = Vec::new();
+    for details in indices {
+        match naming.parse(&details.index, prefix) {
+            Some(name) => recognized.push(ElasticsearchIndexInfo {
+                label: details.index,
+                place_type: name.place_type,
+                coverage: name.coverage,
+                private: name.private,
+                date: name.date,
+                count: details.count.and_then(|count| count.parse().ok()),
+                nanos: name.nanos,
                 updated_at: Utc::now(),
-            }
-        })
-        .collect();
-
-    let es_update_info = ElasticsearchInfo {
-        status: ServerStatus::Available,
-        indices,
-        updated_at: Utc::now(),
-        ..es_info
-    };
+            }),
+            None => unrecognized.push(details.index),
+        }
+    }
 
-    Ok(BragiInfo {
-        elastic: Some(es_update_info),
-        ..info
-    })
+    Ok((recognized, unrecognized))
 }
@@ -0,0 +1,88 @@
+use serde::Deserialize;
+use std::convert::Infallible;
+use warp::http::StatusCode;
+use warp::Reply;
+
+use super::model::{BragiInfo, ElasticsearchIndexInfo, PrivateStatus};
+use crate::poller;
+use crate::state::State;
+
+/// The query string of the status endpoints, which filters the indices.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StatusQuery {
+    /// Only keep the private (true) or the public (false) indices.
+    pub private: Option<bool>,
+    pub coverage: Option<String>,
+    pub place_type: Option<String>,
+}
+
+impl StatusQuery {
+    /// Parse a query string, which may be empty, or answer why it is invalid.
+    fn parse(query: &str) -> Result<Self, Box<dyn Reply>> {
+        serde_urlencoded::from_str(query).map_err(|err| {
+            Box::new(warp::reply::with_status(
+                format!("Invalid query '{}': {}", query, err),
+                StatusCode::BAD_REQUEST,
+            )) as Box<dyn Reply>
+        })
+    }
+
+    fn matches(&self, index: &ElasticsearchIndexInfo) -> bool {
+        self.private.map_or(true, |private| {
+            private == (index.private == PrivateStatus::Private)
+        }) && self
+            .coverage
+            .as_ref()
+            .map_or(true, |coverage| *coverage == index.coverage)
+            && self
+                .place_type
+                .as_ref()
+                .map_or(true, |place_type| *place_type == index.place_type)
+    }
+
+    fn filter(&self, mut info: BragiInfo) -> BragiInfo {
+        if let Some(elastic) = info.elastic.as_mut() {
+            elastic.indices.retain(|index| self.matches(index));
+        }
+        info
+    }
+}
+
+/// Return the last known status of an environment as JSON.
+pub async fn status(
+    environment: String,
+    state: State,
+    query: String,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let query = match StatusQuery::parse(&query) {
+        Ok(query) => query,
+        Err(reply) => return Ok(reply),
+    };
+    let env = match state.settings.environment(&environment) {
+        Ok(env) => env,
+        Err(err) => {
+            return Ok(Box::new(warp::reply::with_status(
+                err.to_string(),
+                StatusCode::NOT_FOUND,
+            )))
+        }
+    };
+
+    let info = poller::snapshot(&state, env, false).await;
+    Ok(Box::new(warp::reply::json(&query.filter(info))))
+}
+
+/// Return the last known status of all the environments as a JSON array.
+pub async fn statuses(state: State, query: String) -> Result<Box<dyn Reply>, Infallible> {
+    let query = match StatusQuery::parse(&query) {
+        Ok(query) => query,
+        Err(reply) => return Ok(reply),
+    };
+    let infos = poller::snapshots(&state, false)
+        .await
+        .into_iter()
+        .map(|info| query.filter(info))
+        .collect::<Vec<_>>();
+    Ok(Box::new(warp::reply::json(&infos)))
+}
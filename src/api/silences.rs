@@ -0,0 +1,166 @@
+use chrono::{DateTime, Datelike, Duration, Utc};
+use juniper::GraphQLObject;
+use serde::{Deserialize, Serialize};
+
+use super::alerts::Alert;
+use super::gql::Context;
+use crate::error;
+use crate::settings::MaintenanceWindow;
+use crate::state::State;
+use crate::utils;
+
+/// A period during which the notifications of the matching alerts are suppressed. A silence
+/// without environment, rule or coverage matches any environment, rule or coverage.
+#[derive(Debug, Deserialize, Serialize, Clone, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct Silence {
+    pub id: i32,
+    pub environment: Option<String>,
+    pub rule: Option<String>,
+    pub coverage: Option<String>,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub comment: Option<String>,
+}
+
+impl Silence {
+    /// Whether the silence applies to the alert at the given time. A silence of a coverage only
+    /// applies to the alerts about that coverage alone, so that an alert about several coverages
+    /// is still notified for the others.
+    pub fn matches(&self, alert: &Alert, at: DateTime<Utc>) -> bool {
+        self.starts_at <= at
+            && at < self.ends_at
+            && self
+                .environment
+                .as_ref()
+                .map_or(true, |environment| *environment == alert.environment)
+            && self.rule.as_ref().map_or(true, |rule| *rule == alert.rule)
+            && self.coverage.as_deref().map_or(true, |silenced| {
+                !alert.coverages.is_empty()
+                    && alert.coverages.iter().all(|coverage| coverage == silenced)
+            })
+    }
+}
+
+/// The response body for silences
+#[derive(Debug, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct SilencesResponseBody {
+    silences: Vec<Silence>,
+    silences_count: i32,
+}
+
+impl From<Vec<Silence>> for SilencesResponseBody {
+    fn from(silences: Vec<Silence>) -> Self {
+        let silences_count = utils::count(silences.len());
+        Self {
+            silences,
+            silences_count,
+        }
+    }
+}
+
+/// Whether a maintenance window applies to the environment at the given time.
+pub fn in_maintenance(window: &MaintenanceWindow, environment: &str, at: DateTime<Utc>) -> bool {
+    if !window.environments.is_empty() && !window.environments.iter().any(|env| env == environment)
+    {
+        return false;
+    }
+
+    // The window may have started on a previous day.
+    (0..=window.duration.num_days()).any(|days| {
+        let day = at.date() - Duration::days(days);
+        let starts_at = day.and_time(window.start);
+        (window.days.is_empty() || window.days.contains(&day.weekday()))
+            && starts_at.map_or(false, |starts_at| {
+                starts_at <= at && at < starts_at + window.duration
+            })
+    })
+}
+
+/// Tell why the notification of the alert is suppressed, if it is: the alert is silenced, or its
+/// environment is in a maintenance window.
+pub async fn suppression(
+    state: &State,
+    alert: &Alert,
+    at: DateTime<Utc>,
+) -> Result<Option<String>, error::Error> {
+    for window in &state.settings.maintenance {
+        if in_maintenance(window, &alert.environment, at) {
+            return Ok(Some(format!("maintenance window {}", window.name)));
+        }
+    }
+
+    let silences = state.db.silences(Some(at)).await?;
+    Ok(silences
+        .iter()
+        .find(|silence| silence.matches(alert, at))
+        .map(|silence| format!("silence {}", silence.id)))
+}
+
+/// Silence the alerts matching the environment, rule and coverage, from now on, for the given
+/// duration (eg '2h').
+pub async fn silence(
+    context: &Context,
+    environment: Option<String>,
+    rule: Option<String>,
+    coverage: Option<String>,
+    duration: &str,
+    comment: Option<String>,
+) -> Result<Silence, error::Error> {
+    if let Some(environment) = &environment {
+        context.state.settings.environment(environment)?;
+    }
+    if let Some(rule) = &rule {
+        context.state.settings.alert_rule(rule)?;
+    }
+    let starts_at = Utc::now();
+    let ends_at = starts_at
+        .checked_add_signed(utils::parse_duration(duration)?)
+        .ok_or_else(|| error::Error::MiscError {
+            details: format!("Silence of {} ends too far in the future", duration),
+        })?;
+
+    context
+        .state
+        .db
+        .insert_silence(Silence {
+            id: 0,
+            environment,
+            rule,
+            coverage,
+            starts_at,
+            ends_at,
+            comment,
+        })
+        .await
+}
+
+/// End a silence now, and return it.
+pub async fn expire_silence(context: &Context, id: i32) -> Result<Silence, error::Error> {
+    let silence = context
+        .state
+        .db
+        .silence(id)
+        .await?
+        .ok_or(error::Error::UnknownSilence { id: i64::from(id) })?;
+    let ends_at = Utc::now().min(silence.ends_at);
+    context.state.db.end_silence(id, ends_at).await?;
+
+    Ok(Silence { ends_at, ..silence })
+}
+
+/// Retrieve the silences, or only the active ones.
+pub async fn silences(
+    context: &Context,
+    active: Option<bool>,
+) -> Result<SilencesResponseBody, error::Error> {
+    let at = if active.unwrap_or(false) {
+        Some(Utc::now())
+    } else {
+        None
+    };
+    let silences = context.state.db.silences(at).await?;
+
+    Ok(SilencesResponseBody::from(silences))
+}
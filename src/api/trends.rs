@@ -0,0 +1,251 @@
+use chrono::{DateTime, Duration, Utc};
+use juniper::GraphQLObject;
+use serde::{Deserialize, Serialize};
+
+use super::gql::Context;
+use super::model::{ElasticsearchIndexInfo, PrivateStatus};
+use crate::error;
+use crate::utils;
+
+/// The number of documents of an index at a given time, or, once the probe results are
+/// aggregated, over the period starting at that time.
+#[derive(Debug, Deserialize, Serialize, Clone, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct CountPoint {
+    pub index: String,
+    pub private: PrivateStatus,
+    /// The number of documents, or the highest number of documents over the period.
+    pub count: i32,
+    /// The lowest number of documents over the period, for an aggregated point.
+    pub min_count: Option<i32>,
+    pub recorded_at: DateTime<Utc>,
+}
+
+/// The response body for the document counts of a coverage and place type
+#[derive(Debug, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentCountsResponseBody {
+    points: Vec<CountPoint>,
+    points_count: i32,
+}
+
+impl From<Vec<CountPoint>> for DocumentCountsResponseBody {
+    fn from(points: Vec<CountPoint>) -> Self {
+        let points_count = utils::count(points.len());
+        Self {
+            points,
+            points_count,
+        }
+    }
+}
+
+/// A sudden drop in the number of documents, either within an index, or between an index and the
+/// newer index replacing it.
+#[derive(Debug, Deserialize, Serialize, Clone, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct CountDrop {
+    pub environment: String,
+    pub place_type: String,
+    pub coverage: String,
+    pub private: PrivateStatus,
+    pub index: String,
+    pub count: i32,
+    /// The index used as a reference, which is the same index as 'index' for a drop within an
+    /// index.
+    pub previous_index: String,
+    pub previous_count: i32,
+    /// The drop, in percent of the previous count.
+    pub drop_percent: f64,
+    pub detected_at: DateTime<Utc>,
+}
+
+/// The response body for document count drops
+#[derive(Debug, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct CountDropsResponseBody {
+    drops: Vec<CountDrop>,
+    drops_count: i32,
+}
+
+impl From<Vec<CountDrop>> for CountDropsResponseBody {
+    fn from(drops: Vec<CountDrop>) -> Self {
+        let drops_count = utils::count(drops.len());
+        Self { drops, drops_count }
+    }
+}
+
+/// Compare the document counts of the indices with those of the previous probe, and return the
+/// drops above the threshold (in percent). Each index is compared with the newest older index for
+/// the same coverage and place type once that index is gone, and with itself otherwise, so that a
+/// new index is not compared with the index it replaces while it is still being filled.
+pub fn detect_drops(
+    environment: &str,
+    previous: &[ElasticsearchIndexInfo],
+    current: &[ElasticsearchIndexInfo],
+    threshold: f64,
+    detected_at: DateTime<Utc>,
+) -> Vec<CountDrop> {
+    current
+        .iter()
+        .filter_map(|index| {
+            let replaced = previous
+                .iter()
+                .filter(|p| {
+                    p.place_type == index.place_type
+                        && p.coverage == index.coverage
+                        && p.private == index.private
+                        && p.date < index.date
+                })
+                .max_by_key(|p| p.date)
+                .filter(|p| !current.iter().any(|c| c.label == p.label));
+            let reference =
+                replaced.or_else(|| previous.iter().find(|p| p.label == index.label))?;
+            // An unknown count is neither a drop, nor a reference.
+            let (count, previous_count) = (index.count?, reference.count?);
+            if previous_count <= 0 {
+                return None;
+            }
+            let drop_percent =
+                100.0 * (f64::from(previous_count) - f64::from(count)) / f64::from(previous_count);
+            if drop_percent < threshold {
+                return None;
+            }
+            Some(CountDrop {
+                environment: String::from(environment),
+                place_type: index.place_type.clone(),
+                coverage: index.coverage.clone(),
+                private: index.private.clone(),
+                index: index.label.clone(),
+                count,
+                previous_index: reference.label.clone(),
+                previous_count,
+                drop_percent,
+                detected_at,
+            })
+        })
+        .collect()
+}
+
+/// Retrieve the document counts of a coverage and place type, across indices, between 'from' (by
+/// default, a week before 'to') and 'to' (by default, now).
+pub async fn document_counts(
+    context: &Context,
+    environment: &str,
+    coverage: &str,
+    place_type: &str,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+) -> Result<DocumentCountsResponseBody, error::Error> {
+    let env = context.state.settings.environment(environment)?;
+    let to = to.unwrap_or_else(Utc::now);
+    let from = match from {
+        Some(from) => from,
+        None => utils::time_before(to, Duration::weeks(1))?,
+    };
+
+    let points = context
+        .state
+        .db
+        .index_counts(&env.name, coverage, place_type, from, to)
+        .await?;
+
+    Ok(DocumentCountsResponseBody::from(points))
+}
+
+/// Retrieve the document count drops detected in an environment (or all environments) between
+/// 'from' (by default, a week before 'to') and 'to' (by default, now).
+pub async fn document_count_drops(
+    context: &Context,
+    environment: Option<String>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+) -> Result<CountDropsResponseBody, error::Error> {
+    if let Some(environment) = &environment {
+        context.state.settings.environment(environment)?;
+    }
+    let to = to.unwrap_or_else(Utc::now);
+    let from = match from {
+        Some(from) => from,
+        None => utils::time_before(to, Duration::weeks(1))?,
+    };
+
+    let drops = context
+        .state
+        .db
+        .count_drops(environment.as_deref(), from, to)
+        .await?;
+
+    Ok(CountDropsResponseBody::from(drops))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    // An index of October 2020, created on the given day.
+    fn index(day: u32, count: i32) -> ElasticsearchIndexInfo {
+        let date = Utc.ymd(2020, 10, day).and_hms(0, 0, 0);
+        ElasticsearchIndexInfo {
+            label: format!("munin_addr_fr_{}", date.format("%Y%m%d")),
+            place_type: String::from("addr"),
+            coverage: String::from("fr"),
+            private: PrivateStatus::Public,
+            date,
+            count: Some(count),
+            nanos: None,
+            updated_at: date,
+        }
+    }
+
+    fn drops(previous: &[ElasticsearchIndexInfo], current: &[ElasticsearchIndexInfo]) -> Vec<f64> {
+        detect_drops("dev", previous, current, 20.0, Utc::now())
+            .iter()
+            .map(|drop| drop.drop_percent)
+            .collect()
+    }
+
+    #[test]
+    fn should_detect_drop_within_index() {
+        let found = detect_drops("dev", &[index(1, 1000)], &[index(1, 700)], 20.0, Utc::now());
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].index, "munin_addr_fr_20201001");
+        assert_eq!(found[0].previous_index, "munin_addr_fr_20201001");
+        assert!((found[0].drop_percent - 30.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn should_not_compare_index_being_filled_with_the_index_it_replaces() {
+        let old = index(1, 1000);
+
+        // The new index appears, and is filled while the old one is still served.
+        assert!(drops(&[old.clone()], &[old.clone(), index(5, 0)]).is_empty());
+        assert!(drops(&[old.clone(), index(5, 0)], &[old.clone(), index(5, 400)]).is_empty());
+        assert!(drops(&[old.clone(), index(5, 400)], &[old.clone(), index(5, 990)]).is_empty());
+
+        // The old index is deleted once the new one is complete.
+        assert!(drops(&[old, index(5, 990)], &[index(5, 990)]).is_empty());
+    }
+
+    #[test]
+    fn should_detect_drop_once_the_replaced_index_is_gone() {
+        let found = detect_drops(
+            "dev",
+            &[index(1, 1000), index(5, 500)],
+            &[index(5, 500)],
+            20.0,
+            Utc::now(),
+        );
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].index, "munin_addr_fr_20201005");
+        assert_eq!(found[0].previous_index, "munin_addr_fr_20201001");
+        assert!((found[0].drop_percent - 50.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn should_ignore_unknown_counts() {
+        let mut closed = index(1, 0);
+        closed.count = None;
+        assert!(drops(&[index(1, 1000)], &[closed]).is_empty());
+    }
+}
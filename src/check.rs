@@ -0,0 +1,188 @@
+use chrono::{DateTime, Utc};
+use clap::ArgMatches;
+use slog::{info, Logger};
+use snafu::ResultExt;
+
+use bragi_status::api::index::IndexNaming;
+use bragi_status::api::model::{self, BragiInfo, BragiStatus, PrivateStatus};
+use bragi_status::error;
+use bragi_status::notify;
+use bragi_status::settings::{CheckThresholds, Environment, Settings};
+
+// The verdicts of the check, following the Nagios plugin convention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Verdict {
+    Ok,
+    Warning,
+    Critical,
+    Unknown,
+}
+
+impl Verdict {
+    fn code(self) -> i32 {
+        match self {
+            Verdict::Ok => 0,
+            Verdict::Warning => 1,
+            Verdict::Critical => 2,
+            Verdict::Unknown => 3,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Verdict::Ok => "OK",
+            Verdict::Warning => "WARNING",
+            Verdict::Critical => "CRITICAL",
+            Verdict::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+/// Probe an environment once, print its status, and return the Nagios code of the verdict:
+/// 0 (OK), 1 (WARNING), 2 (CRITICAL), or 3 (UNKNOWN) if the environment could not be checked.
+#[allow(clippy::needless_lifetimes)]
+pub async fn run<'a>(matches: &ArgMatches<'a>, logger: Logger) -> i32 {
+    match check(matches, &logger).await {
+        Ok(verdict) => verdict.code(),
+        Err(err) => {
+            println!("{} - {}", Verdict::Unknown.label(), err);
+            Verdict::Unknown.code()
+        }
+    }
+}
+
+#[allow(clippy::needless_lifetimes)]
+async fn check<'a>(matches: &ArgMatches<'a>, logger: &Logger) -> Result<Verdict, error::Error> {
+    let settings = Settings::new(matches)?;
+    let naming = IndexNaming::new(&settings.indices)?;
+    let client = model::client(settings.poller.timeout)?;
+
+    let env = match (matches.value_of("environment"), matches.value_of("url")) {
+        (_, Some(url)) => Environment {
+            name: String::from("check"),
+            url: String::from(url),
+            labels: Vec::new(),
+        },
+        (Some(name), None) => settings.environment(name)?.clone(),
+        (None, None) => {
+            return Err(error::Error::MiscError {
+                details: String::from("Either an environment or a url is required"),
+            })
+        }
+    };
+
+    info!(logger, "Checking {} ({})", env.name, env.url);
+    let info = model::status(&client, &env, &naming).await;
+    let (verdict, problems) = evaluate(&info, &settings.check, Utc::now());
+    let summary = if problems.is_empty() {
+        format!("{} - {}: {:?}", verdict.label(), env.name, info.status)
+    } else {
+        format!(
+            "{} - {}: {}",
+            verdict.label(),
+            env.name,
+            problems.join("; ")
+        )
+    };
+
+    match matches.value_of("output").unwrap_or("table") {
+        "json" => {
+            let json = serde_json::to_string_pretty(&info).context(error::SerdeJSONError {
+                details: String::from("Could not serialize status"),
+            })?;
+            println!("{}", json);
+            eprintln!("{}", summary);
+        }
+        _ => {
+            println!("{}", summary);
+            print_table(&info, Utc::now());
+        }
+    }
+
+    Ok(verdict)
+}
+
+// The verdict is the worst of the problems found: bragi or elasticsearch not available, an index
+// too old, or a probe too slow.
+fn evaluate(
+    info: &BragiInfo,
+    thresholds: &CheckThresholds,
+    now: DateTime<Utc>,
+) -> (Verdict, Vec<String>) {
+    let mut verdict = Verdict::Ok;
+    let mut problems = Vec::new();
+    let mut report = |level: Verdict, problem: String| {
+        verdict = verdict.max(level);
+        problems.push(problem);
+    };
+
+    match info.status {
+        BragiStatus::Available => {}
+        BragiStatus::BragiNotAvailable => {
+            report(Verdict::Critical, String::from("bragi is not available"))
+        }
+        BragiStatus::ElasticsearchNotAvailable => report(
+            Verdict::Critical,
+            String::from("elasticsearch is not available"),
+        ),
+    }
+
+    if let Some(indices) = info.indices() {
+        if indices.is_empty() {
+            report(Verdict::Warning, String::from("no index"));
+        }
+        for index in notify::newest_indices(indices).values().flatten() {
+            let age = (now - index.date).num_days();
+            if age > i64::from(thresholds.index_age_critical) {
+                report(
+                    Verdict::Critical,
+                    format!("{} is {} days old", index.label, age),
+                );
+            } else if age > i64::from(thresholds.index_age_warning) {
+                report(
+                    Verdict::Warning,
+                    format!("{} is {} days old", index.label, age),
+                );
+            }
+        }
+    }
+
+    let latency = info
+        .probes
+        .iter()
+        .map(|probe| probe.latency_ms)
+        .sum::<f64>();
+    if latency > thresholds.latency_critical as f64 {
+        report(Verdict::Critical, format!("probe took {:.0}ms", latency));
+    } else if latency > thresholds.latency_warning as f64 {
+        report(Verdict::Warning, format!("probe took {:.0}ms", latency));
+    }
+
+    (verdict, problems)
+}
+
+fn print_table(info: &BragiInfo, now: DateTime<Utc>) {
+    println!(
+        "{:<20} {:<12} {:<8} {:>12} {:>8}",
+        "COVERAGE", "PLACE TYPE", "PRIVATE", "DOCUMENTS", "AGE"
+    );
+    for (coverage, indices) in notify::newest_indices(info.indices().unwrap_or_default()) {
+        for index in indices {
+            let private = if index.private == PrivateStatus::Private {
+                "yes"
+            } else {
+                "no"
+            };
+            println!(
+                "{:<20} {:<12} {:<8} {:>12} {:>7}d",
+                coverage,
+                index.place_type,
+                private,
+                index
+                    .count
+                    .map_or_else(|| String::from("-"), |count| count.to_string()),
+                (now - index.date).num_days()
+            );
+        }
+    }
+}
@@ -0,0 +1,379 @@
+use chrono::{DateTime, Utc};
+use snafu::ResultExt;
+use sqlx::sqlite::{SqlitePool, SqliteQueryAs};
+use std::fmt;
+
+use crate::api::model::{BragiInfo, PrivateStatus};
+use crate::error;
+use crate::settings;
+
+mod aggregates;
+mod alerts;
+mod dead_letters;
+mod index_counts;
+mod index_events;
+mod notifications;
+mod silences;
+mod status_events;
+
+// The statements creating the schema, executed at startup.
+const SCHEMA: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS snapshots (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        environment TEXT NOT NULL,
+        recorded_at INTEGER NOT NULL,
+        status TEXT NOT NULL,
+        info TEXT NOT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS snapshots_environment_recorded_at
+        ON snapshots (environment, recorded_at)",
+    "CREATE TABLE IF NOT EXISTS index_events (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        environment TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        place_type TEXT NOT NULL,
+        coverage TEXT NOT NULL,
+        private INTEGER NOT NULL,
+        index_name TEXT NOT NULL,
+        previous_index TEXT,
+        occurred_at INTEGER NOT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS index_events_occurred_at ON index_events (occurred_at)",
+    "CREATE TABLE IF NOT EXISTS index_counts (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        environment TEXT NOT NULL,
+        index_name TEXT NOT NULL,
+        place_type TEXT NOT NULL,
+        coverage TEXT NOT NULL,
+        private INTEGER NOT NULL,
+        count INTEGER NOT NULL,
+        recorded_at INTEGER NOT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS index_counts_series
+        ON index_counts (environment, coverage, place_type, recorded_at)",
+    "CREATE TABLE IF NOT EXISTS count_drops (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        environment TEXT NOT NULL,
+        place_type TEXT NOT NULL,
+        coverage TEXT NOT NULL,
+        private INTEGER NOT NULL,
+        index_name TEXT NOT NULL,
+        count INTEGER NOT NULL,
+        previous_index TEXT NOT NULL,
+        previous_count INTEGER NOT NULL,
+        drop_percent REAL NOT NULL,
+        detected_at INTEGER NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS status_events (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        environment TEXT NOT NULL,
+        recorded_at INTEGER NOT NULL,
+        info TEXT NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS alerts (
+        rule TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        environment TEXT NOT NULL,
+        state TEXT NOT NULL,
+        summary TEXT NOT NULL,
+        since INTEGER NOT NULL,
+        PRIMARY KEY (rule, environment)
+    )",
+    "CREATE TABLE IF NOT EXISTS alert_tracking (
+        rule TEXT NOT NULL,
+        environment TEXT NOT NULL,
+        failing INTEGER NOT NULL,
+        streak INTEGER NOT NULL,
+        flapping INTEGER NOT NULL,
+        PRIMARY KEY (rule, environment)
+    )",
+    "CREATE TABLE IF NOT EXISTS alert_transitions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        rule TEXT NOT NULL,
+        environment TEXT NOT NULL,
+        state TEXT NOT NULL,
+        occurred_at INTEGER NOT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS alert_transitions_occurred_at
+        ON alert_transitions (rule, environment, occurred_at)",
+    "CREATE TABLE IF NOT EXISTS notifications (
+        dedup_key TEXT PRIMARY KEY,
+        notified_at INTEGER NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS alert_coverages (
+        rule TEXT NOT NULL,
+        environment TEXT NOT NULL,
+        since INTEGER NOT NULL,
+        coverage TEXT NOT NULL,
+        PRIMARY KEY (rule, environment, since, coverage)
+    )",
+    "CREATE TABLE IF NOT EXISTS acknowledgements (
+        rule TEXT NOT NULL,
+        environment TEXT NOT NULL,
+        since INTEGER NOT NULL,
+        acknowledged_at INTEGER NOT NULL,
+        comment TEXT,
+        PRIMARY KEY (rule, environment, since)
+    )",
+    "CREATE TABLE IF NOT EXISTS silences (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        environment TEXT,
+        rule TEXT,
+        coverage TEXT,
+        starts_at INTEGER NOT NULL,
+        ends_at INTEGER NOT NULL,
+        comment TEXT
+    )",
+    "CREATE TABLE IF NOT EXISTS dead_letters (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        channel TEXT NOT NULL,
+        payload TEXT NOT NULL,
+        error TEXT NOT NULL,
+        failed_at INTEGER NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS uptime_aggregates (
+        environment TEXT NOT NULL,
+        resolution TEXT NOT NULL,
+        period_start INTEGER NOT NULL,
+        bragi_observed INTEGER NOT NULL,
+        bragi_downtime INTEGER NOT NULL,
+        bragi_outages INTEGER NOT NULL,
+        elasticsearch_observed INTEGER NOT NULL,
+        elasticsearch_downtime INTEGER NOT NULL,
+        elasticsearch_outages INTEGER NOT NULL,
+        PRIMARY KEY (environment, resolution, period_start)
+    )",
+    "CREATE TABLE IF NOT EXISTS index_count_aggregates (
+        environment TEXT NOT NULL,
+        resolution TEXT NOT NULL,
+        period_start INTEGER NOT NULL,
+        index_name TEXT NOT NULL,
+        place_type TEXT NOT NULL,
+        coverage TEXT NOT NULL,
+        private INTEGER NOT NULL,
+        samples INTEGER NOT NULL,
+        min_count INTEGER NOT NULL,
+        max_count INTEGER NOT NULL,
+        PRIMARY KEY (environment, resolution, period_start, index_name)
+    )",
+];
+
+// Private indices are stored with a flag.
+fn private_flag(private: &PrivateStatus) -> i32 {
+    match private {
+        PrivateStatus::Private => 1,
+        PrivateStatus::Public => 0,
+    }
+}
+
+fn private_status(private: i32) -> PrivateStatus {
+    if private != 0 {
+        PrivateStatus::Private
+    } else {
+        PrivateStatus::Public
+    }
+}
+
+// The id of the last inserted row, which must be read on the connection used for the insertion.
+#[derive(sqlx::FromRow)]
+struct IdRecord {
+    id: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct SnapshotRecord {
+    info: String,
+}
+
+/// The status of an environment at a given time (in seconds since the epoch).
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Sample {
+    pub recorded_at: i64,
+    pub status: String,
+}
+
+/// A notification which could not be delivered (the time it failed in seconds since the epoch).
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct DeadLetter {
+    pub channel: String,
+    pub payload: String,
+    pub error: String,
+    pub failed_at: i64,
+}
+
+/// The storage of probe results.
+#[derive(Clone)]
+pub struct Db {
+    pool: SqlitePool,
+}
+
+impl fmt::Debug for Db {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Db").finish()
+    }
+}
+
+impl Db {
+    /// Connect to the database, and create the schema if needed.
+    pub async fn new(settings: &settings::Database) -> Result<Self, error::Error> {
+        let pool = SqlitePool::new(&settings.url)
+            .await
+            .context(error::DBError {
+                details: format!("Could not connect to database {}", settings.url),
+            })?;
+
+        for statement in SCHEMA {
+            sqlx::query(*statement)
+                .execute(&pool)
+                .await
+                .context(error::DBError {
+                    details: String::from("Could not create database schema"),
+                })?;
+        }
+
+        Ok(Db { pool })
+    }
+
+    /// Check that the database answers.
+    pub async fn ping(&self) -> Result<(), error::Error> {
+        sqlx::query("SELECT 1")
+            .execute(&self.pool)
+            .await
+            .context(error::DBError {
+                details: String::from("Could not reach database"),
+            })?;
+
+        Ok(())
+    }
+
+    /// Store the result of a probe.
+    pub async fn insert_snapshot(&self, info: &BragiInfo) -> Result<(), error::Error> {
+        let status = serde_json::to_value(&info.status).context(error::SerdeJSONError {
+            details: String::from("Could not serialize status"),
+        })?;
+        let content = serde_json::to_string(info).context(error::SerdeJSONError {
+            details: String::from("Could not serialize snapshot"),
+        })?;
+
+        sqlx::query(
+            "INSERT INTO snapshots (environment, recorded_at, status, info) VALUES (?, ?, ?, ?)",
+        )
+        .bind(info.environment.clone())
+        .bind(info.updated_at.timestamp())
+        .bind(String::from(status.as_str().unwrap_or_default()))
+        .bind(content)
+        .execute(&self.pool)
+        .await
+        .context(error::DBError {
+            details: format!("Could not store snapshot for {}", info.environment),
+        })?;
+
+        Ok(())
+    }
+
+    /// Retrieve the results of the probes of an environment made between 'from' and 'to', in
+    /// chronological order.
+    pub async fn snapshots(
+        &self,
+        environment: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<BragiInfo>, error::Error> {
+        let records = sqlx::query_as::<_, SnapshotRecord>(
+            "SELECT info FROM snapshots
+             WHERE environment = ? AND recorded_at >= ? AND recorded_at <= ?
+             ORDER BY recorded_at",
+        )
+        .bind(String::from(environment))
+        .bind(from.timestamp())
+        .bind(to.timestamp())
+        .fetch_all(&self.pool)
+        .await
+        .context(error::DBError {
+            details: format!("Could not retrieve snapshots for {}", environment),
+        })?;
+
+        records
+            .iter()
+            .map(|record| {
+                serde_json::from_str(&record.info).context(error::SerdeJSONError {
+                    details: String::from("Could not deserialize snapshot"),
+                })
+            })
+            .collect()
+    }
+
+    /// Retrieve the last stored probe result of an environment, if any.
+    pub async fn latest_snapshot(
+        &self,
+        environment: &str,
+    ) -> Result<Option<BragiInfo>, error::Error> {
+        let record = sqlx::query_as::<_, SnapshotRecord>(
+            "SELECT info FROM snapshots WHERE environment = ?
+             ORDER BY recorded_at DESC, id DESC LIMIT 1",
+        )
+        .bind(String::from(environment))
+        .fetch_optional(&self.pool)
+        .await
+        .context(error::DBError {
+            details: format!("Could not retrieve last snapshot for {}", environment),
+        })?;
+
+        record
+            .map(|record| {
+                serde_json::from_str(&record.info).context(error::SerdeJSONError {
+                    details: String::from("Could not deserialize snapshot"),
+                })
+            })
+            .transpose()
+    }
+
+    /// Retrieve the last stored probe result of an environment in which bragi and elasticsearch
+    /// were available, if any.
+    pub async fn latest_available_snapshot(
+        &self,
+        environment: &str,
+    ) -> Result<Option<BragiInfo>, error::Error> {
+        let record = sqlx::query_as::<_, SnapshotRecord>(
+            "SELECT info FROM snapshots WHERE environment = ? AND status = 'available'
+             ORDER BY recorded_at DESC, id DESC LIMIT 1",
+        )
+        .bind(String::from(environment))
+        .fetch_optional(&self.pool)
+        .await
+        .context(error::DBError {
+            details: format!("Could not retrieve latest snapshot for {}", environment),
+        })?;
+
+        record
+            .map(|record| {
+                serde_json::from_str(&record.info).context(error::SerdeJSONError {
+                    details: String::from("Could not deserialize snapshot"),
+                })
+            })
+            .transpose()
+    }
+
+    /// Retrieve the statuses of an environment recorded between 'from' and 'to', in chronological
+    /// order.
+    pub async fn samples(
+        &self,
+        environment: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Sample>, error::Error> {
+        sqlx::query_as::<_, Sample>(
+            "SELECT recorded_at, status FROM snapshots
+             WHERE environment = ? AND recorded_at >= ? AND recorded_at <= ?
+             ORDER BY recorded_at",
+        )
+        .bind(String::from(environment))
+        .bind(from.timestamp())
+        .bind(to.timestamp())
+        .fetch_all(&self.pool)
+        .await
+        .context(error::DBError {
+            details: format!("Could not retrieve samples for {}", environment),
+        })
+    }
+}
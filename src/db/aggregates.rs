@@ -0,0 +1,320 @@
+use chrono::{DateTime, Utc};
+use snafu::ResultExt;
+use sqlx::pool::PoolConnection;
+use sqlx::sqlite::SqliteQueryAs;
+use sqlx::{SqliteConnection, Transaction};
+
+use super::{Db, Sample};
+use crate::api::availability::{self, Uptime};
+use crate::error;
+
+const HOUR: i64 = 3600;
+const DAY: i64 = 24 * HOUR;
+
+type Tx = Transaction<PoolConnection<SqliteConnection>>;
+
+#[derive(sqlx::FromRow)]
+struct EnvironmentRecord {
+    environment: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct UptimeRecord {
+    resolution: String,
+    period_start: i64,
+    bragi_observed: i64,
+    bragi_downtime: i64,
+    bragi_outages: i64,
+    elasticsearch_observed: i64,
+    elasticsearch_downtime: i64,
+    elasticsearch_outages: i64,
+}
+
+impl Db {
+    /// Aggregate hourly the probe results and the document counts recorded before 'before', which
+    /// must be the start of an hour, then delete them, along with the status changes, and return
+    /// the number of deleted rows. This is done in a single transaction, so that an interrupted
+    /// run neither loses the results nor aggregates them twice.
+    pub async fn compact_raw(
+        &self,
+        before: DateTime<Utc>,
+        max_gap: i64,
+    ) -> Result<u64, error::Error> {
+        let mut tx = self.pool.begin().await.context(error::DBError {
+            details: String::from("Could not start compaction"),
+        })?;
+
+        let environments = sqlx::query_as::<_, EnvironmentRecord>(
+            "SELECT DISTINCT environment FROM snapshots WHERE recorded_at < ?",
+        )
+        .bind(before.timestamp())
+        .fetch_all(&mut tx)
+        .await
+        .context(error::DBError {
+            details: String::from("Could not retrieve environments"),
+        })?;
+
+        for EnvironmentRecord { environment } in environments {
+            let samples = sqlx::query_as::<_, Sample>(
+                "SELECT recorded_at, status FROM snapshots
+                 WHERE environment = ? AND recorded_at < ?
+                 ORDER BY recorded_at",
+            )
+            .bind(environment.clone())
+            .bind(before.timestamp())
+            .fetch_all(&mut tx)
+            .await
+            .context(error::DBError {
+                details: format!("Could not retrieve samples for {}", environment),
+            })?;
+
+            for (start, samples) in hourly(samples) {
+                let (bragi, elasticsearch) =
+                    availability::summarize(&samples, start + HOUR, max_gap);
+                add_uptime_aggregate(&mut tx, &environment, start, bragi, elasticsearch).await?;
+            }
+        }
+
+        sqlx::query(
+            "INSERT INTO index_count_aggregates
+             (environment, resolution, period_start, index_name, place_type, coverage, private,
+              samples, min_count, max_count)
+             SELECT environment, 'hourly', recorded_at - recorded_at % 3600, index_name,
+                    place_type, coverage, private, COUNT(*), MIN(count), MAX(count)
+             FROM index_counts WHERE recorded_at < ?
+             GROUP BY environment, recorded_at - recorded_at % 3600, index_name
+             ON CONFLICT (environment, resolution, period_start, index_name) DO UPDATE SET
+                samples = samples + excluded.samples,
+                min_count = MIN(min_count, excluded.min_count),
+                max_count = MAX(max_count, excluded.max_count)",
+        )
+        .bind(before.timestamp())
+        .execute(&mut tx)
+        .await
+        .context(error::DBError {
+            details: String::from("Could not aggregate document counts"),
+        })?;
+
+        let mut deleted = 0;
+        for table in &["snapshots", "index_counts", "status_events"] {
+            deleted += sqlx::query(&format!("DELETE FROM {} WHERE recorded_at < ?", table))
+                .bind(before.timestamp())
+                .execute(&mut tx)
+                .await
+                .context(error::DBError {
+                    details: format!("Could not prune {}", table),
+                })?;
+        }
+
+        tx.commit().await.context(error::DBError {
+            details: String::from("Could not commit compaction"),
+        })?;
+
+        Ok(deleted)
+    }
+
+    /// Retrieve the total uptime of bragi and of its elasticsearch aggregated for an environment
+    /// over the periods overlapping 'from' to 'to'. The uptime of a period straddling 'from' or
+    /// 'to' is prorated to the part of the period within the window.
+    pub async fn uptime_aggregates(
+        &self,
+        environment: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<(Uptime, Uptime), error::Error> {
+        let records = sqlx::query_as::<_, UptimeRecord>(
+            "SELECT resolution, period_start, bragi_observed, bragi_downtime, bragi_outages,
+                    elasticsearch_observed, elasticsearch_downtime, elasticsearch_outages
+             FROM uptime_aggregates
+             WHERE environment = ? AND period_start < ?
+             AND period_start + CASE resolution WHEN 'daily' THEN ? ELSE ? END > ?",
+        )
+        .bind(String::from(environment))
+        .bind(to.timestamp())
+        .bind(DAY)
+        .bind(HOUR)
+        .bind(from.timestamp())
+        .fetch_all(&self.pool)
+        .await
+        .context(error::DBError {
+            details: format!("Could not retrieve uptime aggregates for {}", environment),
+        })?;
+
+        Ok(records.into_iter().fold(
+            (Uptime::default(), Uptime::default()),
+            |(bragi, elasticsearch), record| {
+                let length = if record.resolution == "daily" {
+                    DAY
+                } else {
+                    HOUR
+                };
+                let overlap = (record.period_start + length).min(to.timestamp())
+                    - record.period_start.max(from.timestamp());
+                (
+                    bragi
+                        + Uptime {
+                            observed: record.bragi_observed,
+                            downtime: record.bragi_downtime,
+                            outages: record.bragi_outages,
+                        }
+                        .prorated(overlap, length),
+                    elasticsearch
+                        + Uptime {
+                            observed: record.elasticsearch_observed,
+                            downtime: record.elasticsearch_downtime,
+                            outages: record.elasticsearch_outages,
+                        }
+                        .prorated(overlap, length),
+                )
+            },
+        ))
+    }
+
+    /// Delete the index events, document count drops, alert transitions, notifications and dead
+    /// letters which occurred before 'before', and return the number of deleted rows.
+    pub async fn prune_events(&self, before: DateTime<Utc>) -> Result<u64, error::Error> {
+        let mut tx = self.pool.begin().await.context(error::DBError {
+            details: String::from("Could not start pruning events"),
+        })?;
+
+        let mut deleted = 0;
+        for (table, column) in &[
+            ("index_events", "occurred_at"),
+            ("count_drops", "detected_at"),
+            ("alert_transitions", "occurred_at"),
+            ("notifications", "notified_at"),
+            ("dead_letters", "failed_at"),
+        ] {
+            deleted += sqlx::query(&format!("DELETE FROM {} WHERE {} < ?", table, column))
+                .bind(before.timestamp())
+                .execute(&mut tx)
+                .await
+                .context(error::DBError {
+                    details: format!("Could not prune {}", table),
+                })?;
+        }
+
+        tx.commit().await.context(error::DBError {
+            details: String::from("Could not commit pruning events"),
+        })?;
+
+        Ok(deleted)
+    }
+
+    /// Merge the hourly aggregates of the periods starting before 'before', which must be the
+    /// start of a day, into daily aggregates, and return the number of merged hourly aggregates.
+    pub async fn downsample_hourly(&self, before: DateTime<Utc>) -> Result<u64, error::Error> {
+        let statements = [
+            "INSERT INTO uptime_aggregates
+             (environment, resolution, period_start, bragi_observed, bragi_downtime,
+              bragi_outages, elasticsearch_observed, elasticsearch_downtime, elasticsearch_outages)
+             SELECT environment, 'daily', period_start - period_start % 86400,
+                    SUM(bragi_observed), SUM(bragi_downtime), SUM(bragi_outages),
+                    SUM(elasticsearch_observed), SUM(elasticsearch_downtime),
+                    SUM(elasticsearch_outages)
+             FROM uptime_aggregates WHERE resolution = 'hourly' AND period_start < ?
+             GROUP BY environment, period_start - period_start % 86400
+             ON CONFLICT (environment, resolution, period_start) DO UPDATE SET
+                bragi_observed = bragi_observed + excluded.bragi_observed,
+                bragi_downtime = bragi_downtime + excluded.bragi_downtime,
+                bragi_outages = bragi_outages + excluded.bragi_outages,
+                elasticsearch_observed = elasticsearch_observed + excluded.elasticsearch_observed,
+                elasticsearch_downtime = elasticsearch_downtime + excluded.elasticsearch_downtime,
+                elasticsearch_outages = elasticsearch_outages + excluded.elasticsearch_outages",
+            "INSERT INTO index_count_aggregates
+             (environment, resolution, period_start, index_name, place_type, coverage, private,
+              samples, min_count, max_count)
+             SELECT environment, 'daily', period_start - period_start % 86400, index_name,
+                    place_type, coverage, private, SUM(samples), MIN(min_count), MAX(max_count)
+             FROM index_count_aggregates WHERE resolution = 'hourly' AND period_start < ?
+             GROUP BY environment, period_start - period_start % 86400, index_name
+             ON CONFLICT (environment, resolution, period_start, index_name) DO UPDATE SET
+                samples = samples + excluded.samples,
+                min_count = MIN(min_count, excluded.min_count),
+                max_count = MAX(max_count, excluded.max_count)",
+        ];
+        let mut tx = self.pool.begin().await.context(error::DBError {
+            details: String::from("Could not start downsampling"),
+        })?;
+        for statement in &statements {
+            sqlx::query(statement)
+                .bind(before.timestamp())
+                .execute(&mut tx)
+                .await
+                .context(error::DBError {
+                    details: String::from("Could not downsample hourly aggregates"),
+                })?;
+        }
+
+        let mut merged = 0;
+        for table in &["uptime_aggregates", "index_count_aggregates"] {
+            merged += sqlx::query(&format!(
+                "DELETE FROM {} WHERE resolution = 'hourly' AND period_start < ?",
+                table
+            ))
+            .bind(before.timestamp())
+            .execute(&mut tx)
+            .await
+            .context(error::DBError {
+                details: format!("Could not prune hourly {}", table),
+            })?;
+        }
+        tx.commit().await.context(error::DBError {
+            details: String::from("Could not commit downsampling"),
+        })?;
+
+        Ok(merged)
+    }
+}
+
+// Add the uptime of bragi and of its elasticsearch to the hourly aggregate of an environment for
+// the period starting at 'period_start'.
+async fn add_uptime_aggregate(
+    tx: &mut Tx,
+    environment: &str,
+    period_start: i64,
+    bragi: Uptime,
+    elasticsearch: Uptime,
+) -> Result<(), error::Error> {
+    sqlx::query(
+        "INSERT INTO uptime_aggregates
+         (environment, resolution, period_start, bragi_observed, bragi_downtime,
+          bragi_outages, elasticsearch_observed, elasticsearch_downtime, elasticsearch_outages)
+         VALUES (?, 'hourly', ?, ?, ?, ?, ?, ?, ?)
+         ON CONFLICT (environment, resolution, period_start) DO UPDATE SET
+            bragi_observed = bragi_observed + excluded.bragi_observed,
+            bragi_downtime = bragi_downtime + excluded.bragi_downtime,
+            bragi_outages = bragi_outages + excluded.bragi_outages,
+            elasticsearch_observed = elasticsearch_observed + excluded.elasticsearch_observed,
+            elasticsearch_downtime = elasticsearch_downtime + excluded.elasticsearch_downtime,
+            elasticsearch_outages = elasticsearch_outages + excluded.elasticsearch_outages",
+    )
+    .bind(String::from(environment))
+    .bind(period_start)
+    .bind(bragi.observed)
+    .bind(bragi.downtime)
+    .bind(bragi.outages)
+    .bind(elasticsearch.observed)
+    .bind(elasticsearch.downtime)
+    .bind(elasticsearch.outages)
+    .execute(&mut *tx)
+    .await
+    .context(error::DBError {
+        details: format!("Could not store uptime aggregate for {}", environment),
+    })?;
+
+    Ok(())
+}
+
+// Group samples in chronological order by hour.
+fn hourly(samples: Vec<Sample>) -> Vec<(i64, Vec<Sample>)> {
+    let mut hours: Vec<(i64, Vec<Sample>)> = Vec::new();
+    for sample in samples {
+        let start = sample.recorded_at - sample.recorded_at.rem_euclid(HOUR);
+        match hours.last_mut() {
+            Some((last, hour)) if *last == start => hour.push(sample),
+            _ => hours.push((start, vec![sample])),
+        }
+    }
+    hours
+}
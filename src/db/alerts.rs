@@ -0,0 +1,294 @@
+use chrono::{DateTime, TimeZone, Utc};
+use snafu::ResultExt;
+use sqlx::sqlite::SqliteQueryAs;
+use std::convert::TryFrom;
+
+use super::Db;
+use crate::api::alerts::{Alert, AlertState, Tracking};
+use crate::error;
+
+#[derive(sqlx::FromRow)]
+struct AlertRecord {
+    rule: String,
+    kind: String,
+    environment: String,
+    state: String,
+    summary: String,
+    coverages: Option<String>,
+    since: i64,
+    acknowledged_at: Option<i64>,
+    acknowledgement: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct TrackingRecord {
+    failing: i32,
+    streak: i64,
+    flapping: i32,
+}
+
+#[derive(sqlx::FromRow)]
+struct TransitionRecord {
+    occurred_at: i64,
+}
+
+// The alerts, with the coverages of their current state (comma separated), and its
+// acknowledgement, if any.
+const ALERTS: &str = "SELECT a.rule, a.kind, a.environment, a.state, a.summary,
+                             (SELECT GROUP_CONCAT(c.coverage, ',') FROM alert_coverages c
+                              WHERE c.rule = a.rule AND c.environment = a.environment
+                              AND c.since = a.since) AS coverages,
+                             a.since, k.acknowledged_at, k.comment AS acknowledgement
+                      FROM alerts a LEFT JOIN acknowledgements k
+                      ON k.rule = a.rule AND k.environment = a.environment AND k.since = a.since";
+
+impl AlertRecord {
+    fn into_alert(self) -> Result<Alert, error::Error> {
+        let state: AlertState = serde_json::from_value(serde_json::Value::String(self.state))
+            .context(error::SerdeJSONError {
+                details: String::from("Could not deserialize alert state"),
+            })?;
+        Ok(Alert {
+            rule: self.rule,
+            kind: self.kind,
+            environment: self.environment,
+            state,
+            summary: self.summary,
+            coverages: self.coverages.map_or_else(Vec::new, |coverages| {
+                let mut coverages = coverages.split(',').map(String::from).collect::<Vec<_>>();
+                coverages.sort();
+                coverages
+            }),
+            since: Utc.timestamp(self.since, 0),
+            acknowledged_at: self
+                .acknowledged_at
+                .map(|acknowledged_at| Utc.timestamp(acknowledged_at, 0)),
+            acknowledgement: self.acknowledgement,
+        })
+    }
+}
+
+impl Db {
+    /// Store the state of an alert, replacing its previous state.
+    pub async fn upsert_alert(&self, alert: &Alert) -> Result<(), error::Error> {
+        let state = serde_json::to_value(&alert.state).context(error::SerdeJSONError {
+            details: String::from("Could not serialize alert state"),
+        })?;
+
+        sqlx::query(
+            "INSERT OR REPLACE INTO alerts (rule, kind, environment, state, summary, since)
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(alert.rule.clone())
+        .bind(alert.kind.clone())
+        .bind(alert.environment.clone())
+        .bind(String::from(state.as_str().unwrap_or_default()))
+        .bind(alert.summary.clone())
+        .bind(alert.since.timestamp())
+        .execute(&self.pool)
+        .await
+        .context(error::DBError {
+            details: format!(
+                "Could not store alert {} for {}",
+                alert.rule, alert.environment
+            ),
+        })?;
+
+        for coverage in &alert.coverages {
+            sqlx::query(
+                "INSERT OR IGNORE INTO alert_coverages (rule, environment, since, coverage)
+                 VALUES (?, ?, ?, ?)",
+            )
+            .bind(alert.rule.clone())
+            .bind(alert.environment.clone())
+            .bind(alert.since.timestamp())
+            .bind(coverage.clone())
+            .execute(&self.pool)
+            .await
+            .context(error::DBError {
+                details: format!(
+                    "Could not store coverages of alert {} for {}",
+                    alert.rule, alert.environment
+                ),
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Retrieve the state of an alert rule for an environment, if it ever fired.
+    pub async fn alert(
+        &self,
+        rule: &str,
+        environment: &str,
+    ) -> Result<Option<Alert>, error::Error> {
+        let record = sqlx::query_as::<_, AlertRecord>(&format!(
+            "{} WHERE a.rule = ? AND a.environment = ?",
+            ALERTS
+        ))
+        .bind(String::from(rule))
+        .bind(String::from(environment))
+        .fetch_optional(&self.pool)
+        .await
+        .context(error::DBError {
+            details: format!("Could not retrieve alert {} for {}", rule, environment),
+        })?;
+
+        record.map(AlertRecord::into_alert).transpose()
+    }
+
+    /// Retrieve the alerts of an environment (or all environments), the most recent first.
+    pub async fn alerts(&self, environment: Option<&str>) -> Result<Vec<Alert>, error::Error> {
+        let records = sqlx::query_as::<_, AlertRecord>(&format!(
+            "{} WHERE (? IS NULL OR a.environment = ?) ORDER BY a.since DESC, a.rule",
+            ALERTS
+        ))
+        .bind(environment.map(String::from))
+        .bind(environment.map(String::from))
+        .fetch_all(&self.pool)
+        .await
+        .context(error::DBError {
+            details: String::from("Could not retrieve alerts"),
+        })?;
+
+        records.into_iter().map(AlertRecord::into_alert).collect()
+    }
+
+    /// Acknowledge the current state of an alert.
+    pub async fn insert_acknowledgement(
+        &self,
+        alert: &Alert,
+        acknowledged_at: DateTime<Utc>,
+        comment: Option<&str>,
+    ) -> Result<(), error::Error> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO acknowledgements (rule, environment, since, acknowledged_at, comment)
+             VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(alert.rule.clone())
+        .bind(alert.environment.clone())
+        .bind(alert.since.timestamp())
+        .bind(acknowledged_at.timestamp())
+        .bind(comment.map(String::from))
+        .execute(&self.pool)
+        .await
+        .context(error::DBError {
+            details: format!(
+                "Could not acknowledge alert {} for {}",
+                alert.rule, alert.environment
+            ),
+        })?;
+
+        Ok(())
+    }
+
+    /// Retrieve how an alert rule behaved over the recent probes of an environment.
+    pub async fn alert_tracking(
+        &self,
+        rule: &str,
+        environment: &str,
+    ) -> Result<Tracking, error::Error> {
+        let record = sqlx::query_as::<_, TrackingRecord>(
+            "SELECT failing, streak, flapping FROM alert_tracking
+             WHERE rule = ? AND environment = ?",
+        )
+        .bind(String::from(rule))
+        .bind(String::from(environment))
+        .fetch_optional(&self.pool)
+        .await
+        .context(error::DBError {
+            details: format!(
+                "Could not retrieve tracking of {} for {}",
+                rule, environment
+            ),
+        })?;
+
+        Ok(record.map_or_else(Tracking::default, |record| Tracking {
+            failing: record.failing != 0,
+            streak: u32::try_from(record.streak).unwrap_or(u32::MAX),
+            flapping: record.flapping != 0,
+        }))
+    }
+
+    /// Store how an alert rule behaved over the recent probes of an environment.
+    pub async fn upsert_alert_tracking(
+        &self,
+        rule: &str,
+        environment: &str,
+        tracking: &Tracking,
+    ) -> Result<(), error::Error> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO alert_tracking (rule, environment, failing, streak, flapping)
+             VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(String::from(rule))
+        .bind(String::from(environment))
+        .bind(i32::from(tracking.failing))
+        .bind(i64::from(tracking.streak))
+        .bind(i32::from(tracking.flapping))
+        .execute(&self.pool)
+        .await
+        .context(error::DBError {
+            details: format!("Could not store tracking of {} for {}", rule, environment),
+        })?;
+
+        Ok(())
+    }
+
+    /// Record that an alert entered its current state.
+    pub async fn insert_alert_transition(&self, alert: &Alert) -> Result<(), error::Error> {
+        let state = serde_json::to_value(&alert.state).context(error::SerdeJSONError {
+            details: String::from("Could not serialize alert state"),
+        })?;
+
+        sqlx::query(
+            "INSERT INTO alert_transitions (rule, environment, state, occurred_at)
+             VALUES (?, ?, ?, ?)",
+        )
+        .bind(alert.rule.clone())
+        .bind(alert.environment.clone())
+        .bind(String::from(state.as_str().unwrap_or_default()))
+        .bind(alert.since.timestamp())
+        .execute(&self.pool)
+        .await
+        .context(error::DBError {
+            details: format!(
+                "Could not store transition of alert {} for {}",
+                alert.rule, alert.environment
+            ),
+        })?;
+
+        Ok(())
+    }
+
+    /// Retrieve the times at which an alert rule changed state for an environment since 'since',
+    /// in chronological order.
+    pub async fn alert_transitions(
+        &self,
+        rule: &str,
+        environment: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<DateTime<Utc>>, error::Error> {
+        let records = sqlx::query_as::<_, TransitionRecord>(
+            "SELECT occurred_at FROM alert_transitions
+             WHERE rule = ? AND environment = ? AND occurred_at >= ?
+             ORDER BY occurred_at, id",
+        )
+        .bind(String::from(rule))
+        .bind(String::from(environment))
+        .bind(since.timestamp())
+        .fetch_all(&self.pool)
+        .await
+        .context(error::DBError {
+            details: format!(
+                "Could not retrieve transitions of {} for {}",
+                rule, environment
+            ),
+        })?;
+
+        Ok(records
+            .into_iter()
+            .map(|record| Utc.timestamp(record.occurred_at, 0))
+            .collect())
+    }
+}
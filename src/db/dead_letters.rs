@@ -0,0 +1,44 @@
+use chrono::{DateTime, Utc};
+use snafu::ResultExt;
+use sqlx::sqlite::SqliteQueryAs;
+
+use super::{Db, DeadLetter};
+use crate::error;
+
+impl Db {
+    /// Store a notification which could not be delivered.
+    pub async fn insert_dead_letter(
+        &self,
+        channel: &str,
+        payload: &str,
+        error: &str,
+        failed_at: DateTime<Utc>,
+    ) -> Result<(), error::Error> {
+        sqlx::query(
+            "INSERT INTO dead_letters (channel, payload, error, failed_at) VALUES (?, ?, ?, ?)",
+        )
+        .bind(String::from(channel))
+        .bind(String::from(payload))
+        .bind(String::from(error))
+        .bind(failed_at.timestamp())
+        .execute(&self.pool)
+        .await
+        .context(error::DBError {
+            details: format!("Could not store dead letter for {}", channel),
+        })?;
+
+        Ok(())
+    }
+
+    /// Retrieve the notifications which could not be delivered, the oldest first.
+    pub async fn dead_letters(&self) -> Result<Vec<DeadLetter>, error::Error> {
+        sqlx::query_as::<_, DeadLetter>(
+            "SELECT channel, payload, error, failed_at FROM dead_letters ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context(error::DBError {
+            details: String::from("Could not retrieve dead letters"),
+        })
+    }
+}
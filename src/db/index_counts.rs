@@ -0,0 +1,192 @@
+use chrono::{DateTime, TimeZone, Utc};
+use snafu::ResultExt;
+use sqlx::sqlite::SqliteQueryAs;
+
+use super::{private_flag, private_status, Db};
+use crate::api::model::ElasticsearchIndexInfo;
+use crate::api::trends::{CountDrop, CountPoint};
+use crate::error;
+
+#[derive(sqlx::FromRow)]
+struct CountPointRecord {
+    index_name: String,
+    private: i32,
+    count: i32,
+    min_count: Option<i32>,
+    recorded_at: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct CountDropRecord {
+    environment: String,
+    place_type: String,
+    coverage: String,
+    private: i32,
+    index_name: String,
+    count: i32,
+    previous_index: String,
+    previous_count: i32,
+    drop_percent: f64,
+    detected_at: i64,
+}
+
+impl Db {
+    /// Store the known document counts of the indices of an environment, all or none of them.
+    pub async fn insert_index_counts(
+        &self,
+        environment: &str,
+        indices: &[ElasticsearchIndexInfo],
+        recorded_at: DateTime<Utc>,
+    ) -> Result<(), error::Error> {
+        let mut tx = self.pool.begin().await.context(error::DBError {
+            details: String::from("Could not start storing document counts"),
+        })?;
+        for (index, count) in indices
+            .iter()
+            .filter_map(|index| index.count.map(|count| (index, count)))
+        {
+            sqlx::query(
+                "INSERT INTO index_counts
+                 (environment, index_name, place_type, coverage, private, count, recorded_at)
+                 VALUES (?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(String::from(environment))
+            .bind(index.label.clone())
+            .bind(index.place_type.clone())
+            .bind(index.coverage.clone())
+            .bind(private_flag(&index.private))
+            .bind(count)
+            .bind(recorded_at.timestamp())
+            .execute(&mut tx)
+            .await
+            .context(error::DBError {
+                details: format!("Could not store document count of {}", index.label),
+            })?;
+        }
+        tx.commit().await.context(error::DBError {
+            details: String::from("Could not commit document counts"),
+        })?;
+
+        Ok(())
+    }
+
+    /// Retrieve the document counts of a coverage and place type, across indices, recorded between
+    /// 'from' and 'to', in chronological order, including the aggregates of the counts which were
+    /// pruned.
+    pub async fn index_counts(
+        &self,
+        environment: &str,
+        coverage: &str,
+        place_type: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<CountPoint>, error::Error> {
+        let records = sqlx::query_as::<_, CountPointRecord>(
+            "SELECT index_name, private, count, NULL AS min_count, recorded_at FROM index_counts
+             WHERE environment = ? AND coverage = ? AND place_type = ?
+             AND recorded_at >= ? AND recorded_at <= ?
+             UNION ALL
+             SELECT index_name, private, max_count AS count, min_count,
+                    period_start AS recorded_at
+             FROM index_count_aggregates
+             WHERE environment = ? AND coverage = ? AND place_type = ?
+             AND period_start >= ? AND period_start <= ?
+             ORDER BY recorded_at, index_name",
+        )
+        .bind(String::from(environment))
+        .bind(String::from(coverage))
+        .bind(String::from(place_type))
+        .bind(from.timestamp())
+        .bind(to.timestamp())
+        .bind(String::from(environment))
+        .bind(String::from(coverage))
+        .bind(String::from(place_type))
+        .bind(from.timestamp())
+        .bind(to.timestamp())
+        .fetch_all(&self.pool)
+        .await
+        .context(error::DBError {
+            details: format!("Could not retrieve document counts for {}", environment),
+        })?;
+
+        Ok(records
+            .into_iter()
+            .map(|record| CountPoint {
+                index: record.index_name,
+                private: private_status(record.private),
+                count: record.count,
+                min_count: record.min_count,
+                recorded_at: Utc.timestamp(record.recorded_at, 0),
+            })
+            .collect())
+    }
+
+    /// Store a document count drop.
+    pub async fn insert_count_drop(&self, drop: &CountDrop) -> Result<(), error::Error> {
+        sqlx::query(
+            "INSERT INTO count_drops
+             (environment, place_type, coverage, private, index_name, count, previous_index,
+              previous_count, drop_percent, detected_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(drop.environment.clone())
+        .bind(drop.place_type.clone())
+        .bind(drop.coverage.clone())
+        .bind(private_flag(&drop.private))
+        .bind(drop.index.clone())
+        .bind(drop.count)
+        .bind(drop.previous_index.clone())
+        .bind(drop.previous_count)
+        .bind(drop.drop_percent)
+        .bind(drop.detected_at.timestamp())
+        .execute(&self.pool)
+        .await
+        .context(error::DBError {
+            details: format!("Could not store document count drop of {}", drop.index),
+        })?;
+
+        Ok(())
+    }
+
+    /// Retrieve the document count drops of an environment (or all environments) detected
+    /// between 'from' and 'to', in chronological order.
+    pub async fn count_drops(
+        &self,
+        environment: Option<&str>,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<CountDrop>, error::Error> {
+        let records = sqlx::query_as::<_, CountDropRecord>(
+            "SELECT environment, place_type, coverage, private, index_name, count, previous_index,
+                    previous_count, drop_percent, detected_at
+             FROM count_drops
+             WHERE (? IS NULL OR environment = ?) AND detected_at >= ? AND detected_at <= ?
+             ORDER BY detected_at, id",
+        )
+        .bind(environment.map(String::from))
+        .bind(environment.map(String::from))
+        .bind(from.timestamp())
+        .bind(to.timestamp())
+        .fetch_all(&self.pool)
+        .await
+        .context(error::DBError {
+            details: String::from("Could not retrieve document count drops"),
+        })?;
+
+        Ok(records
+            .into_iter()
+            .map(|record| CountDrop {
+                environment: record.environment,
+                place_type: record.place_type,
+                coverage: record.coverage,
+                private: private_status(record.private),
+                index: record.index_name,
+                count: record.count,
+                previous_index: record.previous_index,
+                previous_count: record.previous_count,
+                drop_percent: record.drop_percent,
+                detected_at: Utc.timestamp(record.detected_at, 0),
+            })
+            .collect())
+    }
+}
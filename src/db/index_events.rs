@@ -0,0 +1,99 @@
+use chrono::{DateTime, TimeZone, Utc};
+use snafu::ResultExt;
+use sqlx::sqlite::SqliteQueryAs;
+
+use super::{private_flag, private_status, Db};
+use crate::api::lifecycle::{IndexEvent, IndexEventKind};
+use crate::error;
+
+#[derive(sqlx::FromRow)]
+struct IndexEventRecord {
+    environment: String,
+    kind: String,
+    place_type: String,
+    coverage: String,
+    private: i32,
+    index_name: String,
+    previous_index: Option<String>,
+    occurred_at: i64,
+}
+
+impl IndexEventRecord {
+    fn into_event(self) -> Result<IndexEvent, error::Error> {
+        let kind: IndexEventKind = serde_json::from_value(serde_json::Value::String(self.kind))
+            .context(error::SerdeJSONError {
+                details: String::from("Could not deserialize index event kind"),
+            })?;
+        Ok(IndexEvent {
+            environment: self.environment,
+            kind,
+            place_type: self.place_type,
+            coverage: self.coverage,
+            private: private_status(self.private),
+            index: self.index_name,
+            previous_index: self.previous_index,
+            occurred_at: Utc.timestamp(self.occurred_at, 0),
+        })
+    }
+}
+
+impl Db {
+    /// Store a transition in the indices of an environment.
+    pub async fn insert_index_event(&self, event: &IndexEvent) -> Result<(), error::Error> {
+        let kind = serde_json::to_value(&event.kind).context(error::SerdeJSONError {
+            details: String::from("Could not serialize index event kind"),
+        })?;
+
+        sqlx::query(
+            "INSERT INTO index_events
+             (environment, kind, place_type, coverage, private, index_name, previous_index, occurred_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(event.environment.clone())
+        .bind(String::from(kind.as_str().unwrap_or_default()))
+        .bind(event.place_type.clone())
+        .bind(event.coverage.clone())
+        .bind(private_flag(&event.private))
+        .bind(event.index.clone())
+        .bind(event.previous_index.clone())
+        .bind(event.occurred_at.timestamp())
+        .execute(&self.pool)
+        .await
+        .context(error::DBError {
+            details: format!("Could not store index event for {}", event.environment),
+        })?;
+
+        Ok(())
+    }
+
+    /// Retrieve the transitions in the indices of an environment (or all environments) which
+    /// occurred between 'from' and 'to', in chronological order.
+    pub async fn index_events(
+        &self,
+        environment: Option<&str>,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<IndexEvent>, error::Error> {
+        let records = sqlx::query_as::<_, IndexEventRecord>(
+            "SELECT environment, kind, place_type, coverage, private, index_name, previous_index,
+                    occurred_at
+             FROM index_events
+             WHERE (? IS NULL OR environment = ?) AND occurred_at >= ? AND occurred_at <= ?
+             ORDER BY occurred_at, id",
+        )
+        .bind(environment.map(String::from))
+        .bind(environment.map(String::from))
+        .bind(from.timestamp())
+        .bind(to.timestamp())
+        .fetch_all(&self.pool)
+        .await
+        .context(error::DBError {
+            details: String::from("Could not retrieve index events"),
+        })?;
+
+        records
+            .into_iter()
+            .map(IndexEventRecord::into_event)
+            .collect()
+    }
+}
@@ -0,0 +1,32 @@
+use chrono::{DateTime, Utc};
+use snafu::ResultExt;
+
+use super::Db;
+use crate::error;
+
+impl Db {
+    /// Record that the notification identified by 'dedup_key' is sent at 'at', unless it was
+    /// already sent after 'after'. Return whether it is to be sent.
+    pub async fn claim_notification(
+        &self,
+        dedup_key: &str,
+        at: DateTime<Utc>,
+        after: DateTime<Utc>,
+    ) -> Result<bool, error::Error> {
+        let changed = sqlx::query(
+            "INSERT INTO notifications (dedup_key, notified_at) VALUES (?, ?)
+             ON CONFLICT (dedup_key) DO UPDATE SET notified_at = excluded.notified_at
+             WHERE notified_at <= ?",
+        )
+        .bind(String::from(dedup_key))
+        .bind(at.timestamp())
+        .bind(after.timestamp())
+        .execute(&self.pool)
+        .await
+        .context(error::DBError {
+            details: format!("Could not record notification {}", dedup_key),
+        })?;
+
+        Ok(changed > 0)
+    }
+}
@@ -0,0 +1,119 @@
+use chrono::{DateTime, TimeZone, Utc};
+use snafu::ResultExt;
+use sqlx::sqlite::SqliteQueryAs;
+use std::convert::TryFrom;
+
+use super::{Db, IdRecord};
+use crate::api::silences::Silence;
+use crate::error;
+
+#[derive(sqlx::FromRow)]
+struct SilenceRecord {
+    id: i32,
+    environment: Option<String>,
+    rule: Option<String>,
+    coverage: Option<String>,
+    starts_at: i64,
+    ends_at: i64,
+    comment: Option<String>,
+}
+
+impl From<SilenceRecord> for Silence {
+    fn from(record: SilenceRecord) -> Self {
+        Silence {
+            id: record.id,
+            environment: record.environment,
+            rule: record.rule,
+            coverage: record.coverage,
+            starts_at: Utc.timestamp(record.starts_at, 0),
+            ends_at: Utc.timestamp(record.ends_at, 0),
+            comment: record.comment,
+        }
+    }
+}
+
+impl Db {
+    /// Store a silence, and return it with its id.
+    pub async fn insert_silence(&self, silence: Silence) -> Result<Silence, error::Error> {
+        // The id must be read on the connection used for the insertion.
+        let mut conn = self.pool.acquire().await.context(error::DBError {
+            details: String::from("Could not acquire database connection"),
+        })?;
+        sqlx::query(
+            "INSERT INTO silences (environment, rule, coverage, starts_at, ends_at, comment)
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(silence.environment.clone())
+        .bind(silence.rule.clone())
+        .bind(silence.coverage.clone())
+        .bind(silence.starts_at.timestamp())
+        .bind(silence.ends_at.timestamp())
+        .bind(silence.comment.clone())
+        .execute(&mut conn)
+        .await
+        .context(error::DBError {
+            details: String::from("Could not store silence"),
+        })?;
+        let record = sqlx::query_as::<_, IdRecord>("SELECT last_insert_rowid() AS id")
+            .fetch_one(&mut conn)
+            .await
+            .context(error::DBError {
+                details: String::from("Could not retrieve silence id"),
+            })?;
+
+        Ok(Silence {
+            id: i32::try_from(record.id).unwrap_or(i32::MAX),
+            ..silence
+        })
+    }
+
+    /// Retrieve a silence.
+    pub async fn silence(&self, id: i32) -> Result<Option<Silence>, error::Error> {
+        let record = sqlx::query_as::<_, SilenceRecord>(
+            "SELECT id, environment, rule, coverage, starts_at, ends_at, comment FROM silences
+             WHERE id = ?",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .context(error::DBError {
+            details: format!("Could not retrieve silence {}", id),
+        })?;
+
+        Ok(record.map(Silence::from))
+    }
+
+    /// Retrieve the silences active at the given time (or all silences), the most recent first.
+    pub async fn silences(&self, at: Option<DateTime<Utc>>) -> Result<Vec<Silence>, error::Error> {
+        let at = at.map(|at| at.timestamp());
+        let records = sqlx::query_as::<_, SilenceRecord>(
+            "SELECT id, environment, rule, coverage, starts_at, ends_at, comment FROM silences
+             WHERE ? IS NULL OR (starts_at <= ? AND ? < ends_at)
+             ORDER BY starts_at DESC, id DESC",
+        )
+        .bind(at)
+        .bind(at)
+        .bind(at)
+        .fetch_all(&self.pool)
+        .await
+        .context(error::DBError {
+            details: String::from("Could not retrieve silences"),
+        })?;
+
+        Ok(records.into_iter().map(Silence::from).collect())
+    }
+
+    /// Change the end of a silence.
+    pub async fn end_silence(&self, id: i32, ends_at: DateTime<Utc>) -> Result<(), error::Error> {
+        sqlx::query("UPDATE silences SET ends_at = ? WHERE id = ?")
+            .bind(ends_at.timestamp())
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .context(error::DBError {
+                details: format!("Could not end silence {}", id),
+            })?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+use snafu::ResultExt;
+use sqlx::sqlite::SqliteQueryAs;
+
+use super::{Db, IdRecord};
+use crate::api::events::StatusChange;
+use crate::api::model::BragiInfo;
+use crate::error;
+
+#[derive(sqlx::FromRow)]
+struct StatusEventRecord {
+    id: i64,
+    info: String,
+}
+
+impl Db {
+    /// Store a status change, and return its id.
+    pub async fn insert_status_event(&self, info: &BragiInfo) -> Result<i64, error::Error> {
+        let content = serde_json::to_string(info).context(error::SerdeJSONError {
+            details: String::from("Could not serialize status change"),
+        })?;
+
+        // The id must be read on the connection used for the insertion.
+        let mut conn = self.pool.acquire().await.context(error::DBError {
+            details: String::from("Could not acquire database connection"),
+        })?;
+        sqlx::query("INSERT INTO status_events (environment, recorded_at, info) VALUES (?, ?, ?)")
+            .bind(info.environment.clone())
+            .bind(info.updated_at.timestamp())
+            .bind(content)
+            .execute(&mut conn)
+            .await
+            .context(error::DBError {
+                details: format!("Could not store status change for {}", info.environment),
+            })?;
+        let record = sqlx::query_as::<_, IdRecord>("SELECT last_insert_rowid() AS id")
+            .fetch_one(&mut conn)
+            .await
+            .context(error::DBError {
+                details: String::from("Could not retrieve status change id"),
+            })?;
+
+        Ok(record.id)
+    }
+
+    /// Retrieve the status changes of an environment (or all environments) following the change
+    /// with the given id, in order.
+    pub async fn status_events(
+        &self,
+        environment: Option<&str>,
+        after: i64,
+    ) -> Result<Vec<StatusChange>, error::Error> {
+        let records = sqlx::query_as::<_, StatusEventRecord>(
+            "SELECT id, info FROM status_events
+             WHERE (? IS NULL OR environment = ?) AND id > ?
+             ORDER BY id",
+        )
+        .bind(environment.map(String::from))
+        .bind(environment.map(String::from))
+        .bind(after)
+        .fetch_all(&self.pool)
+        .await
+        .context(error::DBError {
+            details: String::from("Could not retrieve status changes"),
+        })?;
+
+        records
+            .into_iter()
+            .map(|record| {
+                let info = serde_json::from_str(&record.info).context(error::SerdeJSONError {
+                    details: String::from("Could not deserialize status change"),
+                })?;
+                Ok(StatusChange {
+                    id: Some(record.id),
+                    info,
+                })
+            })
+            .collect()
+    }
+}
@@ -15,6 +15,10 @@ pub enum Error {
         source: config::ConfigError,
     },
 
+    #[snafu(display("Invalid settings: {}", details))]
+    #[snafu(visibility(pub))]
+    InvalidSettings { details: String },
+
     #[snafu(display("Environment Variable Error: {} => {}", details, source))]
     #[snafu(visibility(pub))]
     EnvVarError {
@@ -69,6 +73,27 @@ pub enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display("TOML Deserialization Error: {} => {}", details, source))]
+    #[snafu(visibility(pub))]
+    TomlDeError {
+        details: String,
+        source: toml::de::Error,
+    },
+
+    #[snafu(display("TOML Serialization Error: {} => {}", details, source))]
+    #[snafu(visibility(pub))]
+    TomlSerError {
+        details: String,
+        source: toml::ser::Error,
+    },
+
+    #[snafu(display("DB Error: {} => {}", details, source))]
+    #[snafu(visibility(pub))]
+    DBError {
+        details: String,
+        source: sqlx::Error,
+    },
+
     #[snafu(display("Parse Int Error: {} => {}", details, source))]
     #[snafu(visibility(pub))]
     ParseIntError {
@@ -80,12 +105,69 @@ pub enum Error {
     #[snafu(visibility(pub))]
     NotAccessible { url: String, source: reqwest::Error },
 
-    // FIXME Not sure how to specify the source type here,
-    // it's a serde deserialization error, but it requires a lifetime...
-    #[snafu(display("JSON Status not readable {}", url))]
+    #[snafu(display("Response not readable {}", url))]
     #[snafu(visibility(pub))]
     NotReadable { url: String, source: reqwest::Error },
 
+    #[snafu(display("Unexpected HTTP status {} from {}", status, url))]
+    #[snafu(visibility(pub))]
+    UnexpectedStatus { url: String, status: u16 },
+
+    #[snafu(display("JSON response not deserializable {}: {}", url, source))]
+    #[snafu(visibility(pub))]
+    NotDeserializable {
+        url: String,
+        source: serde_json::error::Error,
+    },
+
+    #[snafu(display("Email Error: {} => {}", details, source))]
+    #[snafu(visibility(pub))]
+    EmailError {
+        details: String,
+        source: lettre_email::error::Error,
+    },
+
+    #[snafu(display("SMTP Error: {} => {}", details, source))]
+    #[snafu(visibility(pub))]
+    SmtpError {
+        details: String,
+        source: lettre::smtp::error::Error,
+    },
+
+    #[snafu(display("TLS Error: {} => {}", details, source))]
+    #[snafu(visibility(pub))]
+    TlsError {
+        details: String,
+        source: native_tls::Error,
+    },
+
+    #[snafu(display("Prometheus Error: {} => {}", details, source))]
+    #[snafu(visibility(pub))]
+    PrometheusError {
+        details: String,
+        source: prometheus::Error,
+    },
+
+    #[snafu(display("Unknown environment {}", name))]
+    #[snafu(visibility(pub))]
+    UnknownEnvironment { name: String },
+
+    #[snafu(display("Unknown alert rule {}", name))]
+    #[snafu(visibility(pub))]
+    UnknownAlertRule { name: String },
+
+    #[snafu(display("Alert {} is not firing in {}", rule, environment))]
+    #[snafu(visibility(pub))]
+    AlertNotFiring { rule: String, environment: String },
+
+    #[snafu(display("Unknown silence {}", id))]
+    #[snafu(visibility(pub))]
+    UnknownSilence { id: i64 },
+
+    #[snafu(display("Invalid index naming scheme '{}': {}", scheme, details))]
+    #[snafu(visibility(pub))]
+    InvalidIndexScheme { scheme: String, details: String },
+
     #[snafu(display("elasticsearch url not parsable {}", url))]
     #[snafu(visibility(pub))]
     ElasticsearchURLNotReadable {
@@ -94,6 +176,42 @@ pub enum Error {
     },
 }
 
+impl Error {
+    /// The name of the variant, eg to count errors by kind.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::MiscError { .. } => "MiscError",
+            Error::ConfigError { .. } => "ConfigError",
+            Error::InvalidSettings { .. } => "InvalidSettings",
+            Error::EnvVarError { .. } => "EnvVarError",
+            Error::IOError { .. } => "IOError",
+            Error::ReqwestError { .. } => "ReqwestError",
+            Error::URLError { .. } => "URLError",
+            Error::TokioIOError { .. } => "TokioIOError",
+            Error::TokioJoinError { .. } => "TokioJoinError",
+            Error::SerdeJSONError { .. } => "SerdeJSONError",
+            Error::TomlDeError { .. } => "TomlDeError",
+            Error::TomlSerError { .. } => "TomlSerError",
+            Error::DBError { .. } => "DBError",
+            Error::ParseIntError { .. } => "ParseIntError",
+            Error::NotAccessible { .. } => "NotAccessible",
+            Error::NotReadable { .. } => "NotReadable",
+            Error::UnexpectedStatus { .. } => "UnexpectedStatus",
+            Error::NotDeserializable { .. } => "NotDeserializable",
+            Error::EmailError { .. } => "EmailError",
+            Error::SmtpError { .. } => "SmtpError",
+            Error::TlsError { .. } => "TlsError",
+            Error::PrometheusError { .. } => "PrometheusError",
+            Error::UnknownEnvironment { .. } => "UnknownEnvironment",
+            Error::UnknownAlertRule { .. } => "UnknownAlertRule",
+            Error::AlertNotFiring { .. } => "AlertNotFiring",
+            Error::UnknownSilence { .. } => "UnknownSilence",
+            Error::InvalidIndexScheme { .. } => "InvalidIndexScheme",
+            Error::ElasticsearchURLNotReadable { .. } => "ElasticsearchURLNotReadable",
+        }
+    }
+}
+
 impl IntoFieldError for Error {
     fn into_field_error(self) -> FieldError {
         match self {
@@ -108,6 +226,13 @@ impl IntoFieldError for Error {
                     graphql_value!({ "internal_error": errmsg }),
                 )
             }
+            err @ Error::InvalidSettings { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new(
+                    "Invalid Settings Error",
+                    graphql_value!({ "internal_error": errmsg }),
+                )
+            }
             err @ Error::EnvVarError { .. } => {
                 let errmsg = format!("{}", err);
                 FieldError::new(
@@ -134,6 +259,16 @@ impl IntoFieldError for Error {
                 FieldError::new("Serde Error", graphql_value!({ "internal_error": errmsg }))
             }
 
+            err @ Error::TomlDeError { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new("TOML Error", graphql_value!({ "internal_error": errmsg }))
+            }
+
+            err @ Error::TomlSerError { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new("TOML Error", graphql_value!({ "internal_error": errmsg }))
+            }
+
             err @ Error::ReqwestError { .. } => {
                 let errmsg = format!("{}", err);
                 FieldError::new(
@@ -155,6 +290,11 @@ impl IntoFieldError for Error {
                 )
             }
 
+            err @ Error::DBError { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new("DB Error", graphql_value!({ "internal_error": errmsg }))
+            }
+
             err @ Error::ParseIntError { .. } => {
                 let errmsg = format!("{}", err);
                 FieldError::new(
@@ -179,6 +319,85 @@ impl IntoFieldError for Error {
                 )
             }
 
+            err @ Error::UnknownEnvironment { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new(
+                    "Unknown Environment Error",
+                    graphql_value!({ "internal_error": errmsg }),
+                )
+            }
+
+            err @ Error::EmailError { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new("Email Error", graphql_value!({ "internal_error": errmsg }))
+            }
+
+            err @ Error::SmtpError { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new("SMTP Error", graphql_value!({ "internal_error": errmsg }))
+            }
+
+            err @ Error::TlsError { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new("TLS Error", graphql_value!({ "internal_error": errmsg }))
+            }
+
+            err @ Error::UnexpectedStatus { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new(
+                    "Unexpected Status Error",
+                    graphql_value!({ "internal_error": errmsg }),
+                )
+            }
+
+            err @ Error::NotDeserializable { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new(
+                    "Not Deserializable Error",
+                    graphql_value!({ "internal_error": errmsg }),
+                )
+            }
+
+            err @ Error::UnknownAlertRule { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new(
+                    "Unknown Alert Rule Error",
+                    graphql_value!({ "internal_error": errmsg }),
+                )
+            }
+
+            err @ Error::AlertNotFiring { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new(
+                    "Alert Not Firing Error",
+                    graphql_value!({ "internal_error": errmsg }),
+                )
+            }
+
+            err @ Error::UnknownSilence { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new(
+                    "Unknown Silence Error",
+                    graphql_value!({ "internal_error": errmsg }),
+                )
+            }
+
+            err @ Error::PrometheusError { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new(
+                    "Prometheus Error",
+                    graphql_value!({ "internal_error": errmsg }),
+                )
+            }
+
+            err @ Error::InvalidIndexScheme { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new(
+                    "Invalid Index Scheme Error",
+                    graphql_value!({ "internal_error": errmsg }),
+                )
+            }
+
             err @ Error::ElasticsearchURLNotReadable { .. } => {
                 let errmsg = format!("{}", err);
                 FieldError::new(
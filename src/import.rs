@@ -0,0 +1,287 @@
+use clap::ArgMatches;
+use slog::{info, warn, Logger};
+use snafu::ResultExt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::value::{Table, Value};
+
+use bragi_status::error;
+
+/// A bragi entry point found in the deployment configuration.
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    name: String,
+    url: String,
+}
+
+#[allow(clippy::needless_lifetimes)]
+pub async fn run<'a>(matches: &ArgMatches<'a>, logger: Logger) -> Result<(), error::Error> {
+    let dir = matches
+        .value_of("directory")
+        .ok_or_else(|| error::Error::MiscError {
+            details: String::from("Missing navitia deployment configuration directory"),
+        })?;
+    let output = PathBuf::from(matches.value_of("output").unwrap_or("config/local.toml"));
+
+    let entries = scan(Path::new(dir), &logger)?;
+    info!(
+        logger,
+        "Found {} bragi entry points in {}",
+        entries.len(),
+        dir
+    );
+
+    // We start from the existing file, if any, so that we only touch the environments section.
+    let mut config = if output.exists() {
+        let content = fs::read_to_string(&output).context(error::IOError {
+            details: format!("Could not read '{}'", output.display()),
+        })?;
+        content.parse::<Value>().context(error::TomlDeError {
+            details: format!("Could not parse '{}'", output.display()),
+        })?
+    } else {
+        Value::Table(Table::new())
+    };
+
+    merge(&mut config, &entries)?;
+
+    let content = toml::to_string(&config).context(error::TomlSerError {
+        details: String::from("Could not serialize environments"),
+    })?;
+    fs::write(&output, content).context(error::IOError {
+        details: format!("Could not write '{}'", output.display()),
+    })?;
+
+    info!(logger, "Environments written to {}", output.display());
+    Ok(())
+}
+
+// Walk the deployment configuration directory, and gather all the bragi entry points.
+// The name of the environment is the path, relative to the directory, of the file in which the
+// entry point was found, eg 'prod_fr' for 'prod/fr.yml', so that files with the same name in
+// different directories give different environments.
+fn scan(root: &Path, logger: &Logger) -> Result<Vec<Entry>, error::Error> {
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let mut paths = fs::read_dir(&dir)
+            .context(error::IOError {
+                details: format!("Could not read directory '{}'", dir.display()),
+            })?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        for path in paths {
+            let is_hidden = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map(|name| name.starts_with('.'))
+                .unwrap_or(true);
+            if is_hidden {
+                continue;
+            }
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            // Binary files or files with invalid UTF8 are not configuration files.
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(_) => continue,
+            };
+            let stem = match environment_name(root, &path) {
+                Some(stem) => stem,
+                None => continue,
+            };
+            let urls = extract_urls(&content)
+                .into_iter()
+                .filter(|url| !entries.iter().any(|entry| &entry.url == url))
+                .collect::<Vec<_>>();
+            if urls.len() > 1 {
+                warn!(
+                    logger,
+                    "Found {} bragi entry points in {}",
+                    urls.len(),
+                    path.display()
+                );
+            }
+            for (idx, url) in urls.into_iter().enumerate() {
+                let name = if idx == 0 {
+                    stem.clone()
+                } else {
+                    format!("{}_{}", stem, idx + 1)
+                };
+                if entries.iter().any(|entry| entry.name == name) {
+                    return Err(error::Error::MiscError {
+                        details: format!(
+                            "Environment {} found in {} is already defined by another file",
+                            name,
+                            path.display()
+                        ),
+                    });
+                }
+                entries.push(Entry { name, url });
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+// The name of the environment of a file: its path relative to the root, without extension, with
+// '_' between the directories.
+fn environment_name(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?.with_extension("");
+    let components = relative
+        .iter()
+        .map(|component| component.to_str())
+        .collect::<Option<Vec<_>>>()?;
+    Some(components.join("_"))
+}
+
+// Extract the urls assigned to a bragi related key, eg
+// env.bragi_url = 'http://bragi-ws.ctp.dev.canaltp.fr'
+// "bragi": "http://vippriv-bragi-ws.mutu.prod.canaltp.prod/"
+fn extract_urls(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| {
+            let (key, value) = match line.find(|c: char| c == '=' || c == ':') {
+                Some(pos) if !line[..pos].contains("http") => (&line[..pos], &line[pos + 1..]),
+                _ => return None,
+            };
+            if !key.to_lowercase().contains("bragi") {
+                return None;
+            }
+            let start = value.find("http://").or_else(|| value.find("https://"))?;
+            let url = value[start..]
+                .split(|c: char| c.is_whitespace() || c == '\'' || c == '"' || c == ',')
+                .next()?
+                .trim_end_matches('/');
+            Some(String::from(url))
+        })
+        .collect()
+}
+
+// Merge the entries in the 'environments' array of the configuration: Existing environments have
+// their url updated (their labels are kept), new environments are appended.
+fn merge(config: &mut Value, entries: &[Entry]) -> Result<(), error::Error> {
+    let table = config
+        .as_table_mut()
+        .ok_or_else(|| error::Error::MiscError {
+            details: String::from("Configuration is not a table"),
+        })?;
+
+    let environments = table
+        .entry("environments")
+        .or_insert_with(|| Value::Array(Vec::new()))
+        .as_array_mut()
+        .ok_or_else(|| error::Error::MiscError {
+            details: String::from("'environments' is not an array"),
+        })?;
+
+    for entry in entries {
+        let existing = environments
+            .iter_mut()
+            .find(|env| env.get("name").and_then(Value::as_str) == Some(entry.name.as_str()));
+        match existing.and_then(Value::as_table_mut) {
+            Some(env) => {
+                env.insert(String::from("url"), Value::String(entry.url.clone()));
+            }
+            None => {
+                let mut env = Table::new();
+                env.insert(String::from("name"), Value::String(entry.name.clone()));
+                env.insert(String::from("url"), Value::String(entry.url.clone()));
+                env.insert(String::from("labels"), Value::Array(Vec::new()));
+                environments.push(Value::Table(env));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_extract_bragi_urls() {
+        let content = r#"
+env.bragi_url = 'http://bragi-ws.ctp.dev.canaltp.fr/'
+# env.bragi_url = 'http://commented.out'
+"bragi": "https://vippriv-bragi-ws.mutu.prod.canaltp.prod",
+env.kraken_url = 'http://kraken.ctp.dev.canaltp.fr'
+"#;
+        assert_eq!(
+            extract_urls(content),
+            vec![
+                String::from("http://bragi-ws.ctp.dev.canaltp.fr"),
+                String::from("https://vippriv-bragi-ws.mutu.prod.canaltp.prod"),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_ignore_keys_not_about_bragi() {
+        assert!(extract_urls("url = http://bragi.example.com:9200").is_empty());
+    }
+
+    #[test]
+    fn should_name_environments_after_relative_path() {
+        let root = Path::new("conf");
+        assert_eq!(
+            environment_name(root, Path::new("conf/prod/fr.yml")),
+            Some(String::from("prod_fr"))
+        );
+        assert_eq!(
+            environment_name(root, Path::new("conf/dev.env")),
+            Some(String::from("dev"))
+        );
+    }
+
+    #[test]
+    fn should_merge_entries_keeping_labels() {
+        let mut config = r#"
+[[environments]]
+name = "dev"
+url = "http://old.example.com"
+labels = ["test"]
+"#
+        .parse::<Value>()
+        .unwrap();
+        let entries = vec![
+            Entry {
+                name: String::from("dev"),
+                url: String::from("http://new.example.com"),
+            },
+            Entry {
+                name: String::from("prod"),
+                url: String::from("http://prod.example.com"),
+            },
+        ];
+
+        merge(&mut config, &entries).unwrap();
+
+        let environments = config["environments"].as_array().unwrap();
+        assert_eq!(environments.len(), 2);
+        assert_eq!(
+            environments[0]["url"].as_str(),
+            Some("http://new.example.com")
+        );
+        assert_eq!(
+            environments[0]["labels"].as_array().unwrap(),
+            &vec![Value::String(String::from("test"))]
+        );
+        assert_eq!(environments[1]["name"].as_str(), Some("prod"));
+    }
+
+    #[test]
+    fn should_reject_non_table_environments() {
+        let mut config = "environments = 1".parse::<Value>().unwrap();
+        assert!(merge(&mut config, &[]).is_err());
+    }
+}
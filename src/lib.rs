@@ -1,5 +1,9 @@
 pub mod api;
+pub mod db;
 pub mod error;
+pub mod notify;
+pub mod poller;
+pub mod retention;
 pub mod settings;
 pub mod state;
 pub mod utils;
@@ -1,6 +1,11 @@
 use clap::{App, Arg, SubCommand};
 use slog::{o, warn, Drain};
 
+mod check;
+mod import;
+mod maintenance;
+mod notification;
+mod report;
 mod server;
 
 use bragi_status::error;
@@ -44,6 +49,139 @@ async fn main() -> Result<(), error::Error> {
                         .help("Port"),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("import-envs")
+                .about("Import environments from a navitia deployment configuration")
+                .version("0.1")
+                .author("Matthieu Paindavoine <matt@area403.org>")
+                .arg(
+                    Arg::with_name("directory")
+                        .value_name("DIRECTORY")
+                        .required(true)
+                        .help("navitia deployment configuration directory"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .value_name("FILE")
+                        .short("o")
+                        .long("output")
+                        .help(
+                            "Configuration file to write or merge into (default config/local.toml). \
+                             Its comments and formatting are not preserved",
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("availability")
+                .about("Report the availability of bragi and elasticsearch")
+                .version("0.1")
+                .author("Matthieu Paindavoine <matt@area403.org>")
+                .arg(
+                    Arg::with_name("config")
+                        .value_name("DIRECTORY")
+                        .short("c")
+                        .long("config")
+                        .help("Config directory"),
+                )
+                .arg(
+                    Arg::with_name("environment")
+                        .value_name("NAME")
+                        .short("e")
+                        .long("environment")
+                        .help("Environment (default all environments)"),
+                )
+                .arg(
+                    Arg::with_name("window")
+                        .value_name("DURATION")
+                        .short("w")
+                        .long("window")
+                        .help("Window of time, eg 24h, 30d (default 30d)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("maintenance")
+                .about("Prune and downsample the probe history")
+                .version("0.1")
+                .author("Matthieu Paindavoine <matt@area403.org>")
+                .arg(
+                    Arg::with_name("config")
+                        .value_name("DIRECTORY")
+                        .short("c")
+                        .long("config")
+                        .help("Config directory"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("notify")
+                .about("Send a test alert, or the digest, to the notification channels")
+                .version("0.1")
+                .author("Matthieu Paindavoine <matt@area403.org>")
+                .arg(
+                    Arg::with_name("config")
+                        .value_name("DIRECTORY")
+                        .short("c")
+                        .long("config")
+                        .help("Config directory"),
+                )
+                .arg(
+                    Arg::with_name("channel")
+                        .value_name("NAME")
+                        .short("n")
+                        .long("channel")
+                        .help("Notification channel, a webhook or 'smtp' (default all channels)"),
+                )
+                .arg(
+                    Arg::with_name("environment")
+                        .value_name("NAME")
+                        .short("e")
+                        .long("environment")
+                        .help(
+                            "Environment described in the test alert (default a dummy environment)",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("digest")
+                        .short("d")
+                        .long("digest")
+                        .help("Send the digest instead of a test alert"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Probe an environment once, with Nagios exit codes")
+                .version("0.1")
+                .author("Matthieu Paindavoine <matt@area403.org>")
+                .arg(
+                    Arg::with_name("config")
+                        .value_name("DIRECTORY")
+                        .short("c")
+                        .long("config")
+                        .help("Config directory"),
+                )
+                .arg(
+                    Arg::with_name("environment")
+                        .value_name("NAME")
+                        .short("e")
+                        .long("environment")
+                        .help("Environment to probe"),
+                )
+                .arg(
+                    Arg::with_name("url")
+                        .value_name("URL")
+                        .short("u")
+                        .long("url")
+                        .conflicts_with("environment")
+                        .help("bragi entry point to probe, instead of an environment"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .value_name("FORMAT")
+                        .short("o")
+                        .long("output")
+                        .possible_values(&["json", "table"])
+                        .help("Output format (default table)"),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("test")
                 .about("Test Something")
@@ -59,6 +197,15 @@ async fn main() -> Result<(), error::Error> {
 
     match matches.subcommand() {
         ("run", Some(sm)) => server::run(sm, logger).await,
+        ("import-envs", Some(sm)) => import::run(sm, logger).await,
+        ("availability", Some(sm)) => report::availability(sm, logger).await,
+        ("maintenance", Some(sm)) => maintenance::run(sm, logger).await,
+        ("notify", Some(sm)) => notification::run(sm, logger).await,
+        ("check", Some(sm)) => {
+            // The logger is dropped by 'run', which flushes the queued log lines before exiting.
+            let code = check::run(sm, logger).await;
+            std::process::exit(code)
+        }
         _ => {
             warn!(logger, "Unrecognized subcommand");
             Err(error::Error::MiscError {
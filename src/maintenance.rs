@@ -0,0 +1,33 @@
+use chrono::Utc;
+use clap::ArgMatches;
+use slog::{info, Logger};
+
+use bragi_status::db::Db;
+use bragi_status::error;
+use bragi_status::retention;
+use bragi_status::settings::Settings;
+
+/// Prune and downsample the stored probe results according to the retention settings.
+#[allow(clippy::needless_lifetimes)]
+pub async fn run<'a>(matches: &ArgMatches<'a>, logger: Logger) -> Result<(), error::Error> {
+    let settings = Settings::new(matches)?;
+    let db = Db::new(&settings.database).await?;
+
+    info!(
+        logger,
+        "Keeping raw probe results {} days, hourly aggregates {} months, events {} months",
+        settings.retention.raw_days,
+        settings.retention.hourly_months,
+        settings.retention.events_months
+    );
+    let summary = retention::maintain(&db, &settings, Utc::now()).await?;
+    info!(
+        logger,
+        "Pruned {} rows and {} events, downsampled {} hourly aggregates",
+        summary.pruned,
+        summary.pruned_events,
+        summary.downsampled
+    );
+
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use chrono::Utc;
+use clap::ArgMatches;
+use slog::{info, warn, Logger};
+use std::time::Duration;
+
+use bragi_status::api::alerts::{Alert, AlertState};
+use bragi_status::api::index::IndexNaming;
+use bragi_status::api::model::{self, BragiInfo};
+use bragi_status::error;
+use bragi_status::notify::{email, webhook};
+use bragi_status::settings::{Environment, Settings};
+
+/// Send a test alert, or the digest, to the notification channels, to check their configuration.
+#[allow(clippy::needless_lifetimes)]
+pub async fn run<'a>(matches: &ArgMatches<'a>, logger: Logger) -> Result<(), error::Error> {
+    let settings = Settings::new(matches)?;
+    let naming = IndexNaming::new(&settings.indices)?;
+    let client = model::client(settings.poller.timeout)?;
+    let timeout = Duration::from_secs(settings.notifications.timeout);
+
+    if matches.is_present("digest") {
+        let smtp = settings
+            .notifications
+            .smtp
+            .as_ref()
+            .ok_or_else(|| error::Error::MiscError {
+                details: String::from("No SMTP notification channel"),
+            })?;
+        let infos = model::statuses(&client, &settings.environments, &naming).await;
+        let (subject, body) = email::digest(&infos, Utc::now());
+        email::send(smtp.clone(), timeout, smtp.to.clone(), subject, body).await?;
+        info!(logger, "Sent digest to {}", smtp.to.join(", "));
+        return Ok(());
+    }
+
+    // The alert describes a configured environment as it currently is, or a dummy environment.
+    let info = match matches.value_of("environment") {
+        Some(name) => model::status(&client, settings.environment(name)?, &naming).await,
+        None => BragiInfo::new(&Environment {
+            name: String::from("test"),
+            url: String::new(),
+            labels: Vec::new(),
+        }),
+    };
+    let alert = Alert {
+        rule: String::from("test"),
+        kind: String::from("test"),
+        environment: info.environment.clone(),
+        state: AlertState::Firing,
+        summary: String::from("This is a test notification from bragi-status"),
+        coverages: Vec::new(),
+        since: Utc::now(),
+        acknowledged_at: None,
+        acknowledgement: None,
+    };
+
+    let selected = |name: &str| {
+        matches
+            .value_of("channel")
+            .map_or(true, |channel| channel == name)
+    };
+    let channels = settings
+        .notifications
+        .webhooks
+        .iter()
+        .filter(|channel| selected(&channel.name))
+        .collect::<Vec<_>>();
+    let smtp = settings
+        .notifications
+        .smtp
+        .as_ref()
+        .filter(|_| selected("smtp"));
+    if channels.is_empty() && smtp.is_none() {
+        return Err(error::Error::MiscError {
+            details: String::from("No notification channel"),
+        });
+    }
+
+    for channel in channels {
+        let payload = webhook::payload(channel, &info, &alert)?;
+        match webhook::post(&client, channel, &settings.notifications, &payload).await {
+            Ok(()) => info!(logger, "Notified {}", channel.name),
+            Err(err) => warn!(logger, "Could not notify {}: {}", channel.name, err),
+        }
+    }
+    if let Some(smtp) = smtp {
+        let (subject, body) = email::alert_message(&info, &alert);
+        match email::send(smtp.clone(), timeout, smtp.to.clone(), subject, body).await {
+            Ok(()) => info!(logger, "Notified smtp"),
+            Err(err) => warn!(logger, "Could not notify smtp: {}", err),
+        }
+    }
+
+    Ok(())
+}
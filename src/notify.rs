@@ -0,0 +1,355 @@
+use chrono::Utc;
+use futures::Future;
+use slog::{info, warn, Logger};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::time;
+
+use crate::api::alerts::{Alert, AlertState};
+use crate::api::model::{self, BragiInfo, ElasticsearchIndexInfo};
+use crate::api::silences;
+use crate::db::Db;
+use crate::error;
+use crate::poller;
+use crate::settings::{Notifications, Smtp, Webhook};
+use crate::state::State;
+
+// The longest time between two attempts to deliver a notification.
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+pub mod chat;
+pub mod email;
+pub mod webhook;
+
+#[cfg(test)]
+mod fixtures;
+
+/// Notify the alert, unless it is silenced, its environment is in a maintenance window, or the
+/// same notification was sent within the deduplication window.
+pub async fn notify(state: &State, info: &BragiInfo, alert: &Alert) {
+    match silences::suppression(state, alert, info.updated_at).await {
+        Ok(Some(reason)) => {
+            info!(
+                state.logger,
+                "Alert {} not notified: {}", alert.rule, reason
+            );
+            return;
+        }
+        Ok(None) => {}
+        Err(err) => warn!(state.logger, "Could not check silences: {}", err),
+    }
+
+    let now = Utc::now();
+    let claimed = state
+        .db
+        .claim_notification(
+            &alert.dedup_key(),
+            now,
+            now - state.settings.alerting.dedup_window,
+        )
+        .await;
+    match claimed {
+        Ok(true) => dispatch(state, info, alert),
+        Ok(false) => info!(
+            state.logger,
+            "Alert {} not notified: already notified", alert.rule
+        ),
+        Err(err) => {
+            warn!(state.logger, "Could not deduplicate notification: {}", err);
+            dispatch(state, info, alert);
+        }
+    }
+}
+
+/// Notify the alert, and the status of the environment which triggered it, on all the channels, in
+/// the background, so that an unresponsive channel does not delay the probes.
+pub fn dispatch(state: &State, info: &BragiInfo, alert: &Alert) {
+    for channel in &state.settings.notifications.webhooks {
+        tokio::spawn(notify_webhook(
+            state.clone(),
+            channel.clone(),
+            info.clone(),
+            alert.clone(),
+        ));
+    }
+    if let Some(smtp) = &state.settings.notifications.smtp {
+        tokio::spawn(notify_email(
+            state.clone(),
+            smtp.clone(),
+            info.clone(),
+            alert.clone(),
+        ));
+    }
+}
+
+/// Send the digest of the status of the environments by email every day at the time given in the
+/// settings. This never returns, unless there is no digest to send, and is meant to be spawned.
+pub async fn run_digest(state: State) {
+    let smtp = match &state.settings.notifications.smtp {
+        Some(smtp) => smtp.clone(),
+        None => return,
+    };
+    let at = match smtp.digest_at {
+        Some(at) => at,
+        None => return,
+    };
+
+    loop {
+        let now = Utc::now();
+        let today = now.date().and_time(at).unwrap_or(now);
+        let next = if today > now {
+            today
+        } else {
+            today + chrono::Duration::days(1)
+        };
+        time::delay_for((next - now).to_std().unwrap_or_default()).await;
+
+        let infos = poller::snapshots(&state, false).await;
+        let (subject, body) = email::digest(&infos, Utc::now());
+        let settings = &state.settings.notifications;
+        let res = with_retries(settings, || {
+            email::send(
+                smtp.clone(),
+                Duration::from_secs(settings.timeout),
+                smtp.to.clone(),
+                subject.clone(),
+                body.clone(),
+            )
+        })
+        .await;
+        match res {
+            Ok(()) => info!(state.logger, "Sent digest to {}", smtp.to.join(", ")),
+            Err(err) => warn!(state.logger, "Could not send digest: {}", err),
+        }
+    }
+}
+
+/// Call 'f' until it succeeds, at most 'max_attempts' times, with an exponential backoff (at most
+/// MAX_BACKOFF). On failure, the error of the last attempt is returned.
+pub async fn with_retries<F, Fut>(settings: &Notifications, mut f: F) -> Result<(), error::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<(), error::Error>>,
+{
+    let mut backoff = Duration::from_millis(settings.initial_backoff).min(MAX_BACKOFF);
+    let mut attempt = 1;
+    loop {
+        match f().await {
+            Ok(()) => return Ok(()),
+            Err(err) if attempt >= settings.max_attempts => return Err(err),
+            Err(_) => {
+                time::delay_for(backoff).await;
+                backoff = backoff
+                    .checked_mul(2)
+                    .map_or(MAX_BACKOFF, |backoff| backoff.min(MAX_BACKOFF));
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// The label of the state of an alert in the notifications, eg 'FIRING'.
+pub fn state_label(state: &AlertState) -> &'static str {
+    match state {
+        AlertState::Firing => "FIRING",
+        AlertState::Resolved => "RESOLVED",
+        AlertState::Flapping => "FLAPPING",
+    }
+}
+
+/// The version of a server, or 'unknown' if it could not be retrieved.
+pub fn or_unknown(version: &str) -> &str {
+    if version.is_empty() {
+        "unknown"
+    } else {
+        version
+    }
+}
+
+/// For each coverage, the newest index of each place type, in alphabetical order.
+pub fn newest_indices(
+    indices: &[ElasticsearchIndexInfo],
+) -> BTreeMap<&str, Vec<&ElasticsearchIndexInfo>> {
+    let newest = model::newest_indices_by(indices, |index| {
+        (index.coverage.as_str(), index.place_type.as_str())
+    });
+
+    let mut coverages: BTreeMap<&str, Vec<&ElasticsearchIndexInfo>> = BTreeMap::new();
+    for ((coverage, _), index) in newest {
+        coverages.entry(coverage).or_default().push(index);
+    }
+    coverages
+}
+
+// Deliver the alert to the webhook, and keep it in the dead letters if it could not be delivered.
+async fn notify_webhook(state: State, channel: Webhook, info: BragiInfo, alert: Alert) {
+    let payload = match webhook::payload(&channel, &info, &alert) {
+        Ok(payload) => payload,
+        Err(err) => {
+            warn!(
+                state.logger,
+                "Could not build notification for {}: {}", channel.name, err
+            );
+            return;
+        }
+    };
+
+    let res = webhook::post(
+        &state.client,
+        &channel,
+        &state.settings.notifications,
+        &payload,
+    )
+    .await;
+    report(
+        &state.logger,
+        &state.db,
+        &channel.name,
+        &alert,
+        &payload,
+        res,
+    )
+    .await;
+}
+
+// Send the alert by email, and keep it in the dead letters if it could not be delivered.
+async fn notify_email(state: State, smtp: Smtp, info: BragiInfo, alert: Alert) {
+    let (subject, body) = email::alert_message(&info, &alert);
+    let settings = &state.settings.notifications;
+    let res = with_retries(settings, || {
+        email::send(
+            smtp.clone(),
+            Duration::from_secs(settings.timeout),
+            smtp.to.clone(),
+            subject.clone(),
+            body.clone(),
+        )
+    })
+    .await;
+    let payload = format!("{}\n\n{}", subject, body);
+    report(&state.logger, &state.db, "smtp", &alert, &payload, res).await;
+}
+
+// Log the outcome of the delivery of a notification, and keep it in the dead letters if it failed.
+async fn report(
+    logger: &Logger,
+    db: &Db,
+    channel: &str,
+    alert: &Alert,
+    payload: &str,
+    res: Result<(), error::Error>,
+) {
+    match res {
+        Ok(()) => info!(
+            logger,
+            "Notified alert {} in {} to {}", alert.rule, alert.environment, channel
+        ),
+        Err(err) => {
+            warn!(
+                logger,
+                "Could not notify alert {} in {} to {}: {}",
+                alert.rule,
+                alert.environment,
+                channel,
+                err
+            );
+            let error = format!("{}", err);
+            if let Err(err) = db
+                .insert_dead_letter(channel, payload, &error, Utc::now())
+                .await
+            {
+                warn!(logger, "Could not store dead letter: {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::fixtures::notifications;
+    use crate::settings::Database;
+    use slog::{o, Discard};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn alert() -> Alert {
+        Alert {
+            rule: String::from("bragi-unreachable"),
+            kind: String::from("unreachable"),
+            environment: String::from("dev"),
+            state: AlertState::Firing,
+            summary: String::from("bragi did not answer"),
+            coverages: Vec::new(),
+            since: Utc::now(),
+            acknowledged_at: None,
+            acknowledgement: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn should_retry_until_success() {
+        let attempts = AtomicU32::new(0);
+        let res = with_retries(&notifications(5), || {
+            let attempt = attempts.fetch_add(1, Ordering::SeqCst) + 1;
+            async move {
+                if attempt < 3 {
+                    Err(error::Error::MiscError {
+                        details: format!("attempt {} failed", attempt),
+                    })
+                } else {
+                    Ok(())
+                }
+            }
+        })
+        .await;
+        assert!(res.is_ok());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn should_return_last_error_after_max_attempts() {
+        let attempts = AtomicU32::new(0);
+        let res = with_retries(&notifications(2), || {
+            let attempt = attempts.fetch_add(1, Ordering::SeqCst) + 1;
+            async move {
+                Err(error::Error::MiscError {
+                    details: format!("attempt {} failed", attempt),
+                })
+            }
+        })
+        .await;
+        assert_eq!(
+            res.map_err(|err| err.to_string()),
+            Err(String::from("Misc Error: attempt 2 failed"))
+        );
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn should_keep_failed_notifications_as_dead_letters() {
+        let path = std::env::temp_dir().join(format!(
+            "bragi-status-dead-letters-{}.db",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let db = Db::new(&Database {
+            url: format!("sqlite://{}", path.display()),
+        })
+        .await
+        .unwrap();
+        let logger = Logger::root(Discard, o!());
+
+        report(&logger, &db, "ops", &alert(), "delivered", Ok(())).await;
+        let failure = error::Error::MiscError {
+            details: String::from("connection refused"),
+        };
+        report(&logger, &db, "ops", &alert(), "lost", Err(failure)).await;
+
+        let letters = db.dead_letters().await.unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(letters.len(), 1);
+        assert_eq!(letters[0].channel, "ops");
+        assert_eq!(letters[0].payload, "lost");
+        assert!(letters[0].error.contains("connection refused"));
+    }
+}
@@ -0,0 +1,205 @@
+use serde_json::{json, Value};
+
+use super::{newest_indices, or_unknown, state_label};
+use crate::api::alerts::Alert;
+use crate::api::model::{BragiInfo, BragiStatus};
+use crate::settings::WebhookFormat;
+
+/// Build a Slack or Mattermost incoming webhook message, made of an attachment describing the
+/// alert and the status of the environment.
+pub fn payload(format: WebhookFormat, info: &BragiInfo, alert: &Alert) -> Value {
+    let title = format!(
+        "[{}] {} in {}",
+        state_label(&alert.state),
+        alert.rule,
+        alert.environment
+    );
+
+    let mut fields = vec![
+        field("Status", &format!("{:?}", info.status), true),
+        field("bragi", or_unknown(&info.version), true),
+    ];
+    if let Some(elastic) = &info.elastic {
+        fields.push(field("elasticsearch", or_unknown(&elastic.version), true));
+    }
+    // Only the indices of the coverages the alert is about are listed, so that the message stays
+    // short in environments with many coverages.
+    if let Some(indices) = info.indices() {
+        let coverages = newest_indices(indices);
+        let about = |coverage: &str| alert.coverages.iter().any(|c| c == coverage);
+        let others = coverages.keys().filter(|coverage| !about(coverage)).count();
+        fields.extend(
+            coverages
+                .iter()
+                .filter(|(coverage, _)| about(coverage))
+                .map(|(coverage, indices)| {
+                    let dates = indices
+                        .iter()
+                        .map(|index| {
+                            format!(
+                                "{} {}",
+                                index.place_type,
+                                index.date.format("%Y-%m-%d %H:%M")
+                            )
+                        })
+                        .collect::<Vec<_>>();
+                    field(coverage, &dates.join("\n"), true)
+                }),
+        );
+        if others > 0 {
+            let title = if alert.coverages.is_empty() {
+                "Coverages"
+            } else {
+                "Other coverages"
+            };
+            fields.push(field(title, &others.to_string(), true));
+        }
+    }
+
+    let attachment = json!({
+        "fallback": format!("{}: {}", title, alert.summary),
+        "color": color(&info.status),
+        "title": title,
+        "title_link": info.url,
+        "text": alert.summary,
+        "fields": fields,
+        "footer": "bragi-status",
+        "ts": alert.since.timestamp(),
+    });
+
+    match format {
+        WebhookFormat::Mattermost => json!({
+            "username": "bragi-status",
+            "attachments": [attachment],
+        }),
+        _ => json!({
+            "text": title,
+            "attachments": [attachment],
+        }),
+    }
+}
+
+fn color(status: &BragiStatus) -> &'static str {
+    match status {
+        BragiStatus::Available => "#2eb886",
+        BragiStatus::ElasticsearchNotAvailable => "#daa038",
+        BragiStatus::BragiNotAvailable => "#a30200",
+    }
+}
+
+fn field(title: &str, value: &str, short: bool) -> Value {
+    json!({ "title": title, "value": value, "short": short })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::alerts::AlertState;
+    use crate::notify::fixtures::{index, info};
+    use chrono::{Duration, TimeZone, Utc};
+
+    fn alert(coverages: &[&str]) -> Alert {
+        Alert {
+            rule: String::from("index-stale"),
+            kind: String::from("index_age"),
+            environment: String::from("dev"),
+            state: AlertState::Firing,
+            summary: String::from("munin_admin_fr is 10 days old"),
+            coverages: coverages
+                .iter()
+                .map(|coverage| String::from(*coverage))
+                .collect(),
+            since: Utc.ymd(2020, 10, 5).and_hms(8, 30, 0),
+            acknowledged_at: None,
+            acknowledgement: None,
+        }
+    }
+
+    fn titles(payload: &Value) -> Vec<&str> {
+        payload["attachments"][0]["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|field| field["title"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn should_color_by_status() {
+        assert_eq!(color(&BragiStatus::Available), "#2eb886");
+        assert_eq!(color(&BragiStatus::ElasticsearchNotAvailable), "#daa038");
+        assert_eq!(color(&BragiStatus::BragiNotAvailable), "#a30200");
+
+        let alert = alert(&[]);
+        let mut info = info(alert.since);
+        info.status = BragiStatus::BragiNotAvailable;
+        let payload = payload(WebhookFormat::Slack, &info, &alert);
+        assert_eq!(payload["attachments"][0]["color"], "#a30200");
+    }
+
+    #[test]
+    fn should_list_only_the_coverages_of_the_alert() {
+        let alert = alert(&["fr"]);
+        let mut info = info(alert.since);
+        if let Some(elastic) = info.elastic.as_mut() {
+            elastic
+                .indices
+                .push(index("be", "admin", alert.since - Duration::days(2)));
+            elastic
+                .indices
+                .push(index("nl", "admin", alert.since - Duration::days(2)));
+        }
+
+        let payload = payload(WebhookFormat::Slack, &info, &alert);
+
+        assert_eq!(
+            titles(&payload),
+            vec!["Status", "bragi", "elasticsearch", "fr", "Other coverages"]
+        );
+        let fields = &payload["attachments"][0]["fields"];
+        assert_eq!(
+            fields[3]["value"],
+            "addr 2020-10-04 08:30\nadmin 2020-10-02 08:30"
+        );
+        assert_eq!(fields[4]["value"], "2");
+    }
+
+    #[test]
+    fn should_count_the_coverages_of_an_alert_about_none() {
+        let alert = alert(&[]);
+        let payload = payload(WebhookFormat::Slack, &info(alert.since), &alert);
+
+        assert_eq!(
+            titles(&payload),
+            vec!["Status", "bragi", "elasticsearch", "Coverages"]
+        );
+        assert_eq!(payload["attachments"][0]["fields"][3]["value"], "1");
+    }
+
+    #[test]
+    fn should_build_slack_message() {
+        let alert = alert(&["fr"]);
+        let payload = payload(WebhookFormat::Slack, &info(alert.since), &alert);
+
+        assert_eq!(payload["text"], "[FIRING] index-stale in dev");
+        assert!(payload.get("username").is_none());
+        let attachment = &payload["attachments"][0];
+        assert_eq!(attachment["title"], "[FIRING] index-stale in dev");
+        assert_eq!(attachment["title_link"], "http://bragi.dev");
+        assert_eq!(attachment["text"], "munin_admin_fr is 10 days old");
+        assert_eq!(attachment["ts"], alert.since.timestamp());
+    }
+
+    #[test]
+    fn should_build_mattermost_message() {
+        let alert = alert(&["fr"]);
+        let payload = payload(WebhookFormat::Mattermost, &info(alert.since), &alert);
+
+        assert_eq!(payload["username"], "bragi-status");
+        assert!(payload.get("text").is_none());
+        assert_eq!(
+            payload["attachments"][0]["fallback"],
+            "[FIRING] index-stale in dev: munin_admin_fr is 10 days old"
+        );
+    }
+}
@@ -0,0 +1,277 @@
+use chrono::{DateTime, Utc};
+use lettre::smtp::authentication::Credentials;
+use lettre::smtp::{ClientSecurity, ClientTlsParameters, SmtpClient};
+use lettre::Transport;
+use lettre_email::EmailBuilder;
+use native_tls::TlsConnector;
+use snafu::ResultExt;
+use std::time::Duration;
+
+use super::{newest_indices, or_unknown, state_label};
+use crate::api::alerts::Alert;
+use crate::api::model::BragiInfo;
+use crate::error;
+use crate::settings::Smtp;
+
+/// Send an email through the mail server, each SMTP command timing out after 'timeout'. The SMTP
+/// client is blocking, so it runs on the blocking thread pool.
+pub async fn send(
+    smtp: Smtp,
+    timeout: Duration,
+    to: Vec<String>,
+    subject: String,
+    body: String,
+) -> Result<(), error::Error> {
+    tokio::task::spawn_blocking(move || send_blocking(&smtp, timeout, &to, subject, body))
+        .await
+        .context(error::TokioJoinError {
+            details: String::from("Could not send email"),
+        })?
+}
+
+fn send_blocking(
+    smtp: &Smtp,
+    timeout: Duration,
+    to: &[String],
+    subject: String,
+    body: String,
+) -> Result<(), error::Error> {
+    let email = to
+        .iter()
+        .fold(EmailBuilder::new(), |builder, to| builder.to(to.as_str()))
+        .from(smtp.from.as_str())
+        .subject(subject)
+        .text(body)
+        .build()
+        .context(error::EmailError {
+            details: String::from("Could not build email"),
+        })?;
+
+    let security = if smtp.starttls {
+        let tls = TlsConnector::new().context(error::TlsError {
+            details: String::from("Could not build TLS connector"),
+        })?;
+        ClientSecurity::Required(ClientTlsParameters::new(smtp.host.clone(), tls))
+    } else {
+        ClientSecurity::None
+    };
+    let client =
+        SmtpClient::new((smtp.host.as_str(), smtp.port), security).context(error::SmtpError {
+            details: format!("Could not connect to {}:{}", smtp.host, smtp.port),
+        })?;
+    let client = client.timeout(Some(timeout));
+    let client = match (&smtp.username, &smtp.password) {
+        (Some(username), Some(password)) => {
+            client.credentials(Credentials::new(username.clone(), password.clone()))
+        }
+        _ => client,
+    };
+
+    client
+        .transport()
+        .send(email.into())
+        .context(error::SmtpError {
+            details: format!("Could not send email through {}", smtp.host),
+        })?;
+
+    Ok(())
+}
+
+/// Build the subject and body of the email notifying the alert.
+pub fn alert_message(info: &BragiInfo, alert: &Alert) -> (String, String) {
+    let subject = format!(
+        "[{}] {} in {}",
+        state_label(&alert.state),
+        alert.rule,
+        alert.environment
+    );
+    let body = format!(
+        "{}\nsince {}\n\n{}",
+        alert.summary,
+        alert.since.to_rfc3339(),
+        describe(info, alert.since)
+    );
+    (subject, body)
+}
+
+/// Build the subject and body of the digest of the status of the environments.
+pub fn digest(infos: &[BragiInfo], now: DateTime<Utc>) -> (String, String) {
+    let subject = format!("bragi status digest {}", now.format("%Y-%m-%d"));
+    let body = infos
+        .iter()
+        .map(|info| describe(info, now))
+        .collect::<Vec<_>>()
+        .join("\n");
+    (subject, body)
+}
+
+// Describe the status of an environment: its versions, and the age of the indices of each
+// coverage.
+fn describe(info: &BragiInfo, now: DateTime<Utc>) -> String {
+    let mut lines = vec![
+        format!("{} ({})", info.environment, info.url),
+        format!("  status: {:?}", info.status),
+        format!("  bragi: {}", or_unknown(&info.version)),
+        format!(
+            "  elasticsearch: {}",
+            or_unknown(
+                info.elastic
+                    .as_ref()
+                    .map(|elastic| elastic.version.as_str())
+                    .unwrap_or_default()
+            )
+        ),
+    ];
+    if let Some(indices) = info.indices() {
+        for (coverage, indices) in newest_indices(indices) {
+            let ages = indices
+                .iter()
+                .map(|index| {
+                    format!(
+                        "{} {} ({} days)",
+                        index.place_type,
+                        index.date.format("%Y-%m-%d %H:%M"),
+                        (now - index.date).num_days()
+                    )
+                })
+                .collect::<Vec<_>>();
+            lines.push(format!("  {}: {}", coverage, ages.join(", ")));
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::alerts::AlertState;
+    use crate::api::model::BragiStatus;
+    use crate::notify::fixtures::info;
+    use chrono::TimeZone;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    // Serve a single SMTP session on an ephemeral port, and return the port, and the session,
+    // which yields the commands and the lines of the message received.
+    fn smtp_stub() -> (u16, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let session = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut received = Vec::new();
+            let mut data = false;
+            let mut line = String::new();
+            stream.write_all(b"220 localhost ESMTP\r\n").unwrap();
+            while reader.read_line(&mut line).unwrap_or(0) > 0 {
+                let command = String::from(line.trim_end());
+                line.clear();
+                let reply = if data {
+                    if command != "." {
+                        received.push(command);
+                        continue;
+                    }
+                    data = false;
+                    "250 OK\r\n"
+                } else if command.starts_with("DATA") {
+                    data = true;
+                    "354 End data with <CR><LF>.<CR><LF>\r\n"
+                } else if command.starts_with("QUIT") {
+                    "221 Bye\r\n"
+                } else {
+                    "250 OK\r\n"
+                };
+                let quit = command.starts_with("QUIT");
+                received.push(command);
+                if stream.write_all(reply.as_bytes()).is_err() || quit {
+                    break;
+                }
+            }
+            received
+        });
+        (port, session)
+    }
+
+    #[tokio::test]
+    async fn should_send_through_smtp_server() {
+        let (port, session) = smtp_stub();
+        let smtp = Smtp {
+            host: String::from("127.0.0.1"),
+            port,
+            starttls: false,
+            username: None,
+            password: None,
+            from: String::from("bragi-status@example.com"),
+            to: vec![String::from("search-team@example.com")],
+            digest_at: None,
+        };
+
+        let res = send(
+            smtp.clone(),
+            Duration::from_secs(5),
+            smtp.to.clone(),
+            String::from("[FIRING] bragi-unreachable in dev"),
+            String::from("bragi did not answer"),
+        )
+        .await;
+        let received = session.join().unwrap();
+
+        assert!(res.is_ok(), "unexpected result {:?}", res);
+        assert!(received
+            .iter()
+            .any(|line| line.starts_with("MAIL FROM:<bragi-status@example.com>")));
+        assert!(received
+            .iter()
+            .any(|line| line.starts_with("RCPT TO:<search-team@example.com>")));
+        assert!(received
+            .iter()
+            .any(|line| line == "Subject: [FIRING] bragi-unreachable in dev"));
+        assert!(received.iter().any(|line| line == "bragi did not answer"));
+    }
+
+    #[test]
+    fn should_describe_alert() {
+        let since = Utc.ymd(2020, 10, 5).and_hms(8, 30, 0);
+        let alert = Alert {
+            rule: String::from("fr-index-stale"),
+            kind: String::from("index_age"),
+            environment: String::from("dev"),
+            state: AlertState::Firing,
+            summary: String::from("munin_admin_fr is 10 days old"),
+            coverages: vec![String::from("fr")],
+            since,
+            acknowledged_at: None,
+            acknowledgement: None,
+        };
+
+        let (subject, body) = alert_message(&info(since), &alert);
+
+        assert_eq!(subject, "[FIRING] fr-index-stale in dev");
+        assert!(
+            body.starts_with("munin_admin_fr is 10 days old\nsince 2020-10-05T08:30:00+00:00\n")
+        );
+        assert!(body.contains("dev (http://bragi.dev)"));
+        assert!(body.contains("  bragi: v1.14.0"));
+        assert!(body.contains("  elasticsearch: 7.10.0"));
+    }
+
+    #[test]
+    fn should_list_newest_indices_in_digest() {
+        let now = Utc.ymd(2020, 10, 5).and_hms(7, 0, 0);
+        let mut unknown = info(now);
+        unknown.environment = String::from("prod");
+        unknown.url = String::from("http://bragi.prod");
+        unknown.status = BragiStatus::BragiNotAvailable;
+        unknown.version = String::new();
+
+        let (subject, body) = digest(&[info(now), unknown], now);
+
+        assert_eq!(subject, "bragi status digest 2020-10-05");
+        assert!(body
+            .contains("  fr: addr 2020-10-04 07:00 (1 days), admin 2020-10-02 07:00 (3 days)\n"));
+        assert!(!body.contains("(10 days)"));
+        assert!(body
+            .contains("prod (http://bragi.prod)\n  status: BragiNotAvailable\n  bragi: unknown"));
+    }
+}
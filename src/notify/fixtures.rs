@@ -0,0 +1,64 @@
+use chrono::{DateTime, Duration, Utc};
+
+use crate::api::model::{
+    BragiInfo, BragiStatus, ElasticsearchIndexInfo, ElasticsearchInfo, PrivateStatus, ServerStatus,
+};
+use crate::settings::{Environment, Notifications};
+
+// The fixtures shared by the tests of the notification channels.
+
+pub fn index(coverage: &str, place_type: &str, date: DateTime<Utc>) -> ElasticsearchIndexInfo {
+    ElasticsearchIndexInfo {
+        label: format!(
+            "munin_{}_{}_{}",
+            place_type,
+            coverage,
+            date.format("%Y%m%d")
+        ),
+        place_type: String::from(place_type),
+        coverage: String::from(coverage),
+        private: PrivateStatus::Public,
+        date,
+        count: Some(1000),
+        nanos: None,
+        updated_at: date,
+    }
+}
+
+// The status of an available environment, with the indices of the 'fr' coverage.
+pub fn info(now: DateTime<Utc>) -> BragiInfo {
+    let mut info = BragiInfo::new(&Environment {
+        name: String::from("dev"),
+        url: String::from("http://bragi.dev"),
+        labels: Vec::new(),
+    });
+    info.status = BragiStatus::Available;
+    info.version = String::from("v1.14.0");
+    info.elastic = Some(ElasticsearchInfo {
+        label: String::from("elasticsearch_dev"),
+        url: String::from("http://elasticsearch.dev:9200"),
+        name: String::from("es"),
+        status: ServerStatus::Available,
+        version: String::from("7.10.0"),
+        indices: vec![
+            index("fr", "admin", now - Duration::days(10)),
+            index("fr", "admin", now - Duration::days(3)),
+            index("fr", "addr", now - Duration::days(1)),
+        ],
+        unrecognized_indices: Vec::new(),
+        index_prefix: String::from("munin"),
+        updated_at: now,
+    });
+    info
+}
+
+// Deliver each notification at most 'max_attempts' times, retrying almost at once.
+pub fn notifications(max_attempts: u32) -> Notifications {
+    Notifications {
+        max_attempts,
+        initial_backoff: 1,
+        timeout: 1,
+        webhooks: Vec::new(),
+        smtp: None,
+    }
+}
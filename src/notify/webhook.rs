@@ -0,0 +1,144 @@
+use snafu::ResultExt;
+use std::time::Duration;
+
+use super::{chat, with_retries};
+use crate::api::alerts::Alert;
+use crate::api::model::BragiInfo;
+use crate::error;
+use crate::settings::{Notifications, Webhook, WebhookFormat};
+
+/// Build the body of the request notifying the alert, given the status of the environment.
+pub fn payload(webhook: &Webhook, info: &BragiInfo, alert: &Alert) -> Result<String, error::Error> {
+    match (webhook.format, &webhook.template) {
+        (WebhookFormat::Raw, Some(template)) => render(template, alert),
+        (WebhookFormat::Raw, None) => serde_json::to_string(alert).context(error::SerdeJSONError {
+            details: String::from("Could not serialize alert"),
+        }),
+        (format, _) => serde_json::to_string(&chat::payload(format, info, alert)).context(
+            error::SerdeJSONError {
+                details: String::from("Could not serialize chat message"),
+            },
+        ),
+    }
+}
+
+// Replace the placeholders of the template by the JSON escaped values of the alert, so that the
+// template can be a JSON document.
+fn render(template: &str, alert: &Alert) -> Result<String, error::Error> {
+    let state = serde_json::to_value(&alert.state).context(error::SerdeJSONError {
+        details: String::from("Could not serialize alert state"),
+    })?;
+    let values = [
+        ("{rule}", alert.rule.clone()),
+        ("{kind}", alert.kind.clone()),
+        ("{environment}", alert.environment.clone()),
+        ("{state}", String::from(state.as_str().unwrap_or_default())),
+        ("{summary}", alert.summary.clone()),
+        ("{since}", alert.since.to_rfc3339()),
+    ];
+    values
+        .iter()
+        .try_fold(String::from(template), |body, (placeholder, value)| {
+            let escaped = serde_json::to_string(value).context(error::SerdeJSONError {
+                details: String::from("Could not escape alert"),
+            })?;
+            Ok(body.replace(placeholder, &escaped[1..escaped.len() - 1]))
+        })
+}
+
+/// POST the payload to the webhook, retrying with an exponential backoff. On failure, the error of
+/// the last attempt is returned.
+pub async fn post(
+    client: &reqwest::Client,
+    webhook: &Webhook,
+    settings: &Notifications,
+    payload: &str,
+) -> Result<(), error::Error> {
+    let timeout = Duration::from_secs(settings.timeout);
+    with_retries(settings, move || send(client, webhook, timeout, payload)).await
+}
+
+async fn send(
+    client: &reqwest::Client,
+    webhook: &Webhook,
+    timeout: Duration,
+    payload: &str,
+) -> Result<(), error::Error> {
+    let request = webhook.headers.iter().fold(
+        client
+            .post(&webhook.url)
+            .timeout(timeout)
+            .header("content-type", "application/json"),
+        |request, (name, value)| request.header(name.as_str(), value.as_str()),
+    );
+    let response =
+        request
+            .body(String::from(payload))
+            .send()
+            .await
+            .context(error::NotAccessible {
+                url: webhook.url.clone(),
+            })?;
+    let status = response.status();
+    if status.is_success() {
+        Ok(())
+    } else {
+        Err(error::Error::UnexpectedStatus {
+            url: webhook.url.clone(),
+            status: status.as_u16(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::fixtures::notifications;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use warp::http::StatusCode;
+    use warp::Filter;
+
+    // Serve a webhook which fails the first 'failures' requests, and count the requests.
+    fn stub(failures: u32) -> (Webhook, Arc<AtomicU32>) {
+        let attempts = Arc::new(AtomicU32::new(0));
+        let counter = attempts.clone();
+        let route = warp::post().map(move || {
+            if counter.fetch_add(1, Ordering::SeqCst) < failures {
+                StatusCode::INTERNAL_SERVER_ERROR
+            } else {
+                StatusCode::OK
+            }
+        });
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let webhook = Webhook {
+            name: String::from("stub"),
+            url: format!("http://{}/", addr),
+            headers: HashMap::new(),
+            format: WebhookFormat::Raw,
+            template: None,
+        };
+        (webhook, attempts)
+    }
+
+    #[tokio::test]
+    async fn should_retry_failed_posts() {
+        let (webhook, attempts) = stub(2);
+        let res = post(&reqwest::Client::new(), &webhook, &notifications(3), "{}").await;
+        assert!(res.is_ok());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn should_give_up_after_max_attempts() {
+        let (webhook, attempts) = stub(u32::MAX);
+        let res = post(&reqwest::Client::new(), &webhook, &notifications(2), "{}").await;
+        match res {
+            Err(error::Error::UnexpectedStatus { status, .. }) => assert_eq!(status, 500),
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,210 @@
+use chrono::Utc;
+use futures::future;
+use slog::{info, warn};
+use std::time::Duration;
+use tokio::time;
+
+use crate::api::alerts;
+use crate::api::events::StatusChange;
+use crate::api::lifecycle;
+use crate::api::model::{self, BragiInfo, BragiStatus};
+use crate::api::trends::{self, CountDrop};
+use crate::notify;
+use crate::settings::Environment;
+use crate::state::State;
+
+/// Probe all the environments at the interval given in the settings, and keep the results in the
+/// state. This never returns, and is meant to be spawned.
+pub async fn run(state: State) {
+    info!(
+        state.logger,
+        "Polling environments every {}s", state.settings.poller.interval
+    );
+    let mut interval = time::interval(Duration::from_secs(state.settings.poller.interval));
+    loop {
+        interval.tick().await;
+        state.heartbeat.start(Utc::now());
+        poll(&state).await;
+        state.heartbeat.complete(Utc::now());
+    }
+}
+
+/// Probe all the environments concurrently, and store the results.
+pub async fn poll(state: &State) -> Vec<BragiInfo> {
+    let infos = model::statuses(&state.client, &state.settings.environments, &state.naming).await;
+    for info in &infos {
+        publish(state, info).await;
+    }
+    infos
+}
+
+/// Probe the environment, and store the result.
+pub async fn refresh(state: &State, env: &Environment) -> BragiInfo {
+    let info = model::status(&state.client, env, &state.naming).await;
+    publish(state, &info).await;
+    info
+}
+
+/// Return the last known status of the environment. The environment is probed if a refresh is
+/// forced, or if it has not been probed yet.
+pub async fn snapshot(state: &State, env: &Environment, force_refresh: bool) -> BragiInfo {
+    if !force_refresh {
+        if let Some(info) = state.snapshots.read().await.get(&env.name) {
+            return info.clone();
+        }
+    }
+    refresh(state, env).await
+}
+
+/// Return the last known status of all the environments.
+pub async fn snapshots(state: &State, force_refresh: bool) -> Vec<BragiInfo> {
+    if force_refresh {
+        return poll(state).await;
+    }
+    future::join_all(
+        state
+            .settings
+            .environments
+            .iter()
+            .map(|env| snapshot(state, env, false)),
+    )
+    .await
+}
+
+// Store the result of a probe, and record what changed since the previous one.
+async fn publish(state: &State, info: &BragiInfo) {
+    if info.status != BragiStatus::Available {
+        warn!(
+            state.logger,
+            "Environment {} is not available: {:?}", info.environment, info.status
+        );
+    }
+    state.metrics.observe_probe(info);
+    let drops = record_index_changes(state, info).await;
+    // After a restart, the previous probe is the last one stored, so that its changes are detected.
+    let cached = state.snapshots.read().await.contains_key(&info.environment);
+    let stored = if cached {
+        None
+    } else {
+        match state.db.latest_snapshot(&info.environment).await {
+            Ok(stored) => stored,
+            Err(err) => {
+                warn!(state.logger, "Could not retrieve last snapshot: {}", err);
+                None
+            }
+        }
+    };
+    if let Err(err) = state.db.insert_snapshot(info).await {
+        warn!(state.logger, "Could not store snapshot: {}", err);
+    }
+    let previous = state
+        .snapshots
+        .write()
+        .await
+        .insert(info.environment.clone(), info.clone())
+        .or(stored);
+    for alert in alerts::evaluate(state, previous.as_ref(), info, &drops).await {
+        warn!(
+            state.logger,
+            "Alert {} {:?} in {}: {}", alert.rule, alert.state, alert.environment, alert.summary
+        );
+        notify::notify(state, info, &alert).await;
+    }
+    if previous.map_or(true, |previous| changed(&previous, info)) {
+        // The change is pushed to the subscribers even if it could not be stored.
+        let id = match state.db.insert_status_event(info).await {
+            Ok(id) => Some(id),
+            Err(err) => {
+                warn!(state.logger, "Could not store status change: {}", err);
+                None
+            }
+        };
+        // There may be no subscriber.
+        let _ = state.changes.send(StatusChange {
+            id,
+            info: info.clone(),
+        });
+    }
+}
+
+// A status changed if the availability of bragi or its elasticsearch, its version, or its indices
+// changed.
+fn changed(previous: &BragiInfo, current: &BragiInfo) -> bool {
+    let labels = |info: &BragiInfo| {
+        let mut labels = info
+            .indices()
+            .unwrap_or_default()
+            .iter()
+            .map(|index| index.label.clone())
+            .collect::<Vec<_>>();
+        labels.sort();
+        labels
+    };
+    previous.status != current.status
+        || previous.version != current.version
+        || labels(previous) != labels(current)
+}
+
+// Compare the indices with those of the last probe in which they could be retrieved, store the
+// transitions, the document counts, and return the document count drops.
+async fn record_index_changes(state: &State, info: &BragiInfo) -> Vec<CountDrop> {
+    let indices = match info.indices() {
+        Some(indices) => indices,
+        None => return Vec::new(),
+    };
+    if let Err(err) = state
+        .db
+        .insert_index_counts(&info.environment, indices, info.updated_at)
+        .await
+    {
+        warn!(state.logger, "Could not store document counts: {}", err);
+    }
+    let previous = match state.db.latest_available_snapshot(&info.environment).await {
+        Ok(Some(previous)) => previous,
+        Ok(None) => return Vec::new(),
+        Err(err) => {
+            warn!(
+                state.logger,
+                "Could not retrieve previous snapshot: {}", err
+            );
+            return Vec::new();
+        }
+    };
+    let previous_indices = previous.indices().unwrap_or_default();
+
+    for event in lifecycle::diff(
+        &info.environment,
+        previous_indices,
+        indices,
+        info.updated_at,
+    ) {
+        info!(
+            state.logger,
+            "Index {} {:?} in {}", event.index, event.kind, event.environment
+        );
+        if let Err(err) = state.db.insert_index_event(&event).await {
+            warn!(state.logger, "Could not store index event: {}", err);
+        }
+    }
+
+    let drops = trends::detect_drops(
+        &info.environment,
+        previous_indices,
+        indices,
+        state.settings.trends.drop_threshold,
+        info.updated_at,
+    );
+    for drop in &drops {
+        warn!(
+            state.logger,
+            "Document count of {} dropped by {:.1}% in {}",
+            drop.index,
+            drop.drop_percent,
+            drop.environment
+        );
+        if let Err(err) = state.db.insert_count_drop(drop).await {
+            warn!(state.logger, "Could not store document count drop: {}", err);
+        }
+    }
+    drops
+}
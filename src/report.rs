@@ -0,0 +1,60 @@
+use chrono::Utc;
+use clap::ArgMatches;
+use slog::{info, Logger};
+
+use bragi_status::api::availability::{self, ServiceAvailability};
+use bragi_status::db::Db;
+use bragi_status::error;
+use bragi_status::settings::Settings;
+use bragi_status::utils;
+
+/// Print the availability of the environments over a window of time.
+#[allow(clippy::needless_lifetimes)]
+pub async fn availability<'a>(
+    matches: &ArgMatches<'a>,
+    logger: Logger,
+) -> Result<(), error::Error> {
+    let settings = Settings::new(matches)?;
+    let db = Db::new(&settings.database).await?;
+
+    let window = matches.value_of("window").unwrap_or("30d");
+    let to = Utc::now();
+    let from = utils::time_before(to, utils::parse_duration(window)?)?;
+    let max_gap = availability::max_gap(settings.poller.interval);
+
+    let environments = match matches.value_of("environment") {
+        Some(name) => vec![settings.environment(name)?.clone()],
+        None => settings.environments.clone(),
+    };
+
+    info!(
+        logger,
+        "Availability of {} environments from {} to {}",
+        environments.len(),
+        from,
+        to
+    );
+
+    println!(
+        "{:<20} {:<14} {:>10} {:>12} {:>8}",
+        "ENVIRONMENT", "SERVICE", "UPTIME", "DOWNTIME", "OUTAGES"
+    );
+    for env in environments {
+        let report = availability::compute(&db, &env.name, from, to, max_gap).await?;
+        print_row(&env.name, "bragi", &report.bragi);
+        print_row(&env.name, "elasticsearch", &report.elasticsearch);
+    }
+
+    Ok(())
+}
+
+fn print_row(environment: &str, service: &str, availability: &ServiceAvailability) {
+    let uptime = availability
+        .uptime
+        .map(|uptime| format!("{:.3}%", uptime))
+        .unwrap_or_else(|| String::from("n/a"));
+    println!(
+        "{:<20} {:<14} {:>10} {:>11}s {:>8}",
+        environment, service, uptime, availability.downtime, availability.outages
+    );
+}
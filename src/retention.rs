@@ -0,0 +1,85 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use slog::{info, warn};
+use tokio::time;
+
+use crate::api::availability;
+use crate::db::Db;
+use crate::error;
+use crate::settings::Settings;
+use crate::state::State;
+
+const HOUR: i64 = 3600;
+const DAY: i64 = 24 * HOUR;
+
+/// What a maintenance run did.
+#[derive(Debug, Default, Clone)]
+pub struct Summary {
+    /// The number of probe results, document counts and status changes deleted.
+    pub pruned: u64,
+    /// The number of index events, document count drops, alert transitions, notifications and
+    /// dead letters deleted.
+    pub pruned_events: u64,
+    /// The number of hourly aggregates merged into daily aggregates.
+    pub downsampled: u64,
+}
+
+/// Run the maintenance at the interval given in the settings. This never returns, and is meant to
+/// be spawned.
+pub async fn run(state: State) {
+    let mut interval = time::interval(std::time::Duration::from_secs(
+        state.settings.retention.interval,
+    ));
+    loop {
+        interval.tick().await;
+        match maintain(&state.db, &state.settings, Utc::now()).await {
+            Ok(summary) => info!(
+                state.logger,
+                "Pruned {} rows and {} events, downsampled {} hourly aggregates",
+                summary.pruned,
+                summary.pruned_events,
+                summary.downsampled
+            ),
+            Err(err) => warn!(state.logger, "Could not run maintenance: {}", err),
+        }
+    }
+}
+
+/// Aggregate hourly, then delete, the probe results older than the retention period, merge the
+/// hourly aggregates older than their retention period into daily aggregates, and delete the
+/// events older than their retention period.
+pub async fn maintain(
+    db: &Db,
+    settings: &Settings,
+    now: DateTime<Utc>,
+) -> Result<Summary, error::Error> {
+    let retention = &settings.retention;
+    let max_gap = availability::max_gap(settings.poller.interval).num_seconds();
+
+    // Only whole periods are aggregated, so that each period is aggregated at once.
+    let raw_cutoff = period_start(now - Duration::days(i64::from(retention.raw_days)), HOUR);
+    let raw_cutoff = Utc.timestamp(raw_cutoff, 0);
+    let pruned = db.compact_raw(raw_cutoff, max_gap).await?;
+
+    let hourly_cutoff = period_start(
+        now - Duration::days(30 * i64::from(retention.hourly_months)),
+        DAY,
+    );
+    let downsampled = db
+        .downsample_hourly(Utc.timestamp(hourly_cutoff, 0))
+        .await?;
+
+    let events_cutoff = now - Duration::days(30 * i64::from(retention.events_months));
+    let pruned_events = db.prune_events(events_cutoff).await?;
+
+    Ok(Summary {
+        pruned,
+        pruned_events,
+        downsampled,
+    })
+}
+
+// The start of the period of the given length (in seconds) containing 'at'.
+fn period_start(at: DateTime<Utc>, length: i64) -> i64 {
+    let timestamp = at.timestamp();
+    timestamp - timestamp.rem_euclid(length)
+}
@@ -1,12 +1,22 @@
 use clap::ArgMatches;
-use juniper_warp::playground_filter;
-use slog::{info, Logger};
+use futures::FutureExt;
+use juniper_graphql_ws::ConnectionConfig;
+use juniper_warp::{playground_filter, subscriptions::serve_graphql_ws};
+use slog::{info, warn, Logger};
 use snafu::ResultExt;
 use std::net::ToSocketAddrs;
+use std::sync::Arc;
 use warp::{self, Filter};
 
+use bragi_status::api::events;
 use bragi_status::api::gql;
+use bragi_status::api::health;
+use bragi_status::api::metrics;
+use bragi_status::api::rest;
 use bragi_status::error;
+use bragi_status::notify;
+use bragi_status::poller;
+use bragi_status::retention;
 use bragi_status::settings::Settings;
 use bragi_status::state::State;
 
@@ -18,6 +28,13 @@ pub async fn run<'a>(matches: &ArgMatches<'a>, logger: Logger) -> Result<(), err
 }
 
 pub async fn run_server(state: State) -> Result<(), error::Error> {
+    // The environments are probed in the background, and the API serves the results.
+    tokio::spawn(poller::run(state.clone()));
+    // The probe history is pruned and downsampled in the background.
+    tokio::spawn(retention::run(state.clone()));
+    // The daily digest, if any, is sent by email.
+    tokio::spawn(notify::run_digest(state.clone()));
+
     // We keep a copy of the logger before the context takes ownership of it.
     let state_cpy = state.clone();
     let qm_state = warp::any().map(move || gql::Context {
@@ -25,12 +42,90 @@ pub async fn run_server(state: State) -> Result<(), error::Error> {
     });
 
     let qm_schema = gql::schema();
+    let graphql_metrics = state.metrics.clone();
     let graphql = warp::post()
         .and(warp::path("graphql"))
         .and(juniper_warp::make_graphql_filter(
             qm_schema,
             qm_state.boxed(),
-        ));
+        ))
+        .with(warp::log::custom(move |info| {
+            graphql_metrics.observe_graphql(info.status().as_u16(), info.elapsed())
+        }));
+
+    let events_state = state.clone();
+    let events = warp::get()
+        .and(warp::path("events"))
+        .and(warp::any().map(move || events_state.clone()))
+        .and(
+            warp::query::<events::EventsQuery>()
+                .or(warp::any().map(events::EventsQuery::default))
+                .unify(),
+        )
+        .and(warp::sse::last_event_id::<i64>())
+        .and_then(events::events);
+
+    let status_state = state.clone();
+    // The query string is parsed by the handlers, so that an invalid one is rejected with its
+    // reason, while a missing one is empty.
+    let status_query = || warp::query::raw().or(warp::any().map(String::new)).unify();
+    let status = warp::get()
+        .and(warp::path!("api" / "v1" / "status" / String))
+        .and(warp::any().map(move || status_state.clone()))
+        .and(status_query())
+        .and_then(rest::status);
+
+    let statuses_state = state.clone();
+    let statuses = warp::get()
+        .and(warp::path!("api" / "v1" / "status"))
+        .and(warp::any().map(move || statuses_state.clone()))
+        .and(status_query())
+        .and_then(rest::statuses);
+
+    let healthz_state = state.clone();
+    let healthz = warp::get()
+        .and(warp::path("healthz"))
+        .and(warp::any().map(move || healthz_state.clone()))
+        .and_then(health::healthz);
+
+    let readyz_state = state.clone();
+    let readyz = warp::get()
+        .and(warp::path("readyz"))
+        .and(warp::any().map(move || readyz_state.clone()))
+        .and_then(health::readyz);
+
+    let metrics_state = state.clone();
+    let metrics = warp::get()
+        .and(warp::path("metrics"))
+        .and(warp::any().map(move || metrics_state.clone()))
+        .and_then(metrics::metrics);
+
+    let root_node = Arc::new(gql::schema());
+    let ws_state = state.clone();
+    let subscriptions = warp::path("subscriptions")
+        .and(warp::ws())
+        .map(move |ws: warp::ws::Ws| {
+            let root_node = root_node.clone();
+            let state = ws_state.clone();
+            ws.on_upgrade(move |websocket| async move {
+                let logger = state.logger.clone();
+                serve_graphql_ws(
+                    websocket,
+                    root_node,
+                    ConnectionConfig::new(gql::Context { state }),
+                )
+                .map(move |res| {
+                    if let Err(err) = res {
+                        warn!(logger, "Websocket error: {}", err);
+                    }
+                })
+                .await
+            })
+        })
+        .map(|reply| {
+            // The graphql-ws protocol must be acknowledged for the playground to connect.
+            warp::reply::with_header(reply, "Sec-WebSocket-Protocol", "graphql-ws")
+        });
 
     let playground = warp::get()
         .and(warp::path("playground"))
@@ -45,7 +140,17 @@ pub async fn run_server(state: State) -> Result<(), error::Error> {
 
     let log = warp::log("foo");
 
-    let routes = playground.or(graphql).with(cors).with(log);
+    let routes = playground
+        .or(graphql)
+        .or(events)
+        .or(status)
+        .or(statuses)
+        .or(metrics)
+        .or(healthz)
+        .or(readyz)
+        .or(subscriptions)
+        .with(cors)
+        .with(log);
 
     let host = state.settings.service.host;
     let port = state.settings.service.port;
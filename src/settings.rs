@@ -1,16 +1,253 @@
+use chrono::{Duration, NaiveTime, Weekday};
 use clap::ArgMatches;
-use config::{Config, Environment, File};
-use serde::Deserialize;
+use config::{Config, Environment as ConfigEnvironment, File};
+use serde::{de, Deserialize, Deserializer};
 use snafu::ResultExt;
+use std::collections::HashMap;
 use std::env;
 use std::path::PathBuf;
 
 use super::error;
+use super::utils;
 
+/// A named bragi deployment (eg prod, dev, ...) watched by this service.
 #[derive(Debug, Clone, Deserialize)]
-pub struct Bragi {
+pub struct Environment {
+    pub name: String,
+    /// The bragi entry point, eg http://bragi-ws.ctp.dev.canaltp.fr
+    pub url: String,
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+/// How to interpret the names of the elasticsearch indices.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Indices {
+    /// The prefix used when bragi's elasticsearch url does not specify one.
+    pub default_prefix: String,
+    /// The naming schemes, tried in order, eg '{prefix}_{place_type}_{coverage}_{date}_{time}'
+    pub schemes: Vec<String>,
+    pub date_format: String,
+    pub time_format: String,
+    /// The marker preceding the coverage of private indices, eg 'priv.'
+    pub private_marker: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Database {
+    /// eg sqlite://bragi-status.db
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Poller {
+    /// The time between two probes of the environments, in seconds.
+    pub interval: u64,
+    /// The timeout of each HTTP call of a probe, in seconds.
+    pub timeout: u64,
+}
+
+/// How long the probe results are kept. Probe results older than 'raw_days' are aggregated
+/// hourly, and hourly aggregates older than 'hourly_months' are aggregated daily. Daily aggregates
+/// are kept forever.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Retention {
+    pub raw_days: u32,
+    pub hourly_months: u32,
+    /// How long the index events, document count drops, alert transitions, notifications and
+    /// dead letters are kept, in months.
+    pub events_months: u32,
+    /// The time between two runs of the maintenance, in seconds.
+    pub interval: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Trends {
+    /// The drop in the number of documents of a coverage and place type, in percent, above which
+    /// a drop is reported.
+    pub drop_threshold: f64,
+}
+
+/// The thresholds of the 'check' subcommand, above which the status of an environment is a warning
+/// or critical.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CheckThresholds {
+    /// The age, in days, of the newest index of a coverage and place type.
+    pub index_age_warning: u32,
+    pub index_age_critical: u32,
+    /// The total latency of the probe, in milliseconds.
+    pub latency_warning: u64,
+    pub latency_critical: u64,
+}
+
+/// The condition under which an alert fires.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AlertCondition {
+    /// bragi did not answer.
+    Unreachable,
+    /// bragi answered, but not its elasticsearch.
+    ElasticsearchUnavailable,
+    /// The newest index of a coverage (and optionally place type) is older than 'max_age_days'.
+    IndexAge {
+        coverage: String,
+        place_type: Option<String>,
+        max_age_days: u32,
+    },
+    /// The document count of an index dropped (see the trends settings).
+    DocumentCountDrop,
+    /// The version of bragi changed since the previous probe.
+    VersionChanged,
+}
+
+impl AlertCondition {
+    /// Whether the condition is met by a single probe only, comparing it with the previous one,
+    /// so that it can never be met by consecutive probes.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            AlertCondition::DocumentCountDrop | AlertCondition::VersionChanged
+        )
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AlertCondition::Unreachable => "unreachable",
+            AlertCondition::ElasticsearchUnavailable => "elasticsearch_unavailable",
+            AlertCondition::IndexAge { .. } => "index_age",
+            AlertCondition::DocumentCountDrop => "document_count_drop",
+            AlertCondition::VersionChanged => "version_changed",
+        }
+    }
+}
+
+/// An alert rule, evaluated after each probe of the environments it applies to.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AlertRule {
+    pub name: String,
+    /// The environments the rule applies to (default all environments).
+    #[serde(default)]
+    pub environments: Vec<String>,
+    #[serde(flatten)]
+    pub condition: AlertCondition,
+    /// The number of consecutive probes meeting the condition for the alert to fire (default 1).
+    /// 'probes' is accepted as an alias. It must be 1 for the conditions met by a single probe
+    /// only, ie 'document_count_drop' and 'version_changed'.
+    #[serde(default = "default_streak", alias = "probes")]
+    pub fire_after: u32,
+    /// The number of consecutive probes not meeting the condition for the alert to resolve
+    /// (default 1).
+    #[serde(default = "default_streak")]
+    pub resolve_after: u32,
+}
+
+fn default_streak() -> u32 {
+    1
+}
+
+impl AlertRule {
+    pub fn applies_to(&self, environment: &str) -> bool {
+        self.environments.is_empty() || self.environments.iter().any(|env| env == environment)
+    }
+}
+
+/// How the changes of state of the alerts are notified.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Alerting {
+    /// The number of changes of state within 'flap_window' from which an alert is flapping. This
+    /// is notified once, then its changes of state are not notified until it is stable for
+    /// 'flap_window'.
+    pub flap_threshold: u32,
+    /// eg '1h'
+    #[serde(deserialize_with = "duration")]
+    pub flap_window: Duration,
+    /// The time during which the same notification (same rule, environment, state, and time at
+    /// which the state was entered) is not sent again, eg after a restart, eg '24h'.
+    #[serde(deserialize_with = "duration")]
+    pub dedup_window: Duration,
+}
+
+/// The body of the requests sent to a webhook.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookFormat {
+    /// The alert as JSON, or the template.
+    Raw,
+    /// A Slack incoming webhook message.
+    Slack,
+    /// A Mattermost incoming webhook message.
+    Mattermost,
+}
+
+impl Default for WebhookFormat {
+    fn default() -> Self {
+        WebhookFormat::Raw
+    }
+}
+
+/// A URL receiving alerts as HTTP POST requests.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Webhook {
+    pub name: String,
+    pub url: String,
+    /// Headers added to each request, eg authorization.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub format: WebhookFormat,
+    /// For the raw format, the request body, in which '{rule}', '{kind}', '{environment}',
+    /// '{state}', '{summary}' and '{since}' are replaced by the JSON escaped value of the alert
+    /// (default the alert as JSON).
+    pub template: Option<String>,
+}
+
+/// A recurring window of time during which alerts are not notified, eg during a planned
+/// reindexing.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MaintenanceWindow {
+    pub name: String,
+    /// The environments the window applies to (default all environments).
+    #[serde(default)]
+    pub environments: Vec<String>,
+    /// The days on which the window starts, eg ["Sat", "Sun"] (default every day).
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    /// The time of day (UTC) at which the window starts, eg '02:00'.
+    #[serde(deserialize_with = "time_of_day")]
+    pub start: NaiveTime,
+    /// The duration of the window, eg '3h'.
+    #[serde(deserialize_with = "duration")]
+    pub duration: Duration,
+}
+
+/// A mail server through which alerts, and the daily digest, are sent.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Smtp {
     pub host: String,
     pub port: u16,
+    /// Whether to upgrade the connection with STARTTLS.
+    pub starttls: bool,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    /// The time of day (UTC) at which the digest is sent, eg '07:00' (default no digest).
+    #[serde(default, deserialize_with = "optional_time_of_day")]
+    pub digest_at: Option<NaiveTime>,
+}
+
+/// How alerts are delivered when they fire or resolve.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Notifications {
+    /// The number of attempts to deliver a notification before giving up.
+    pub max_attempts: u32,
+    /// The time before the first retry, in milliseconds. It doubles at each retry.
+    pub initial_backoff: u64,
+    /// The timeout of each attempt (of each SMTP command for emails), in seconds.
+    pub timeout: u64,
+    #[serde(default)]
+    pub webhooks: Vec<Webhook>,
+    pub smtp: Option<Smtp>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -25,7 +262,52 @@ pub struct Settings {
     pub testing: bool,
     pub mode: String,
     pub service: Service,
-    pub bragi: Bragi,
+    pub indices: Indices,
+    pub poller: Poller,
+    pub database: Database,
+    pub retention: Retention,
+    pub trends: Trends,
+    pub alerting: Alerting,
+    pub check: CheckThresholds,
+    pub notifications: Notifications,
+    #[serde(default)]
+    pub environments: Vec<Environment>,
+    #[serde(default)]
+    pub alerts: Vec<AlertRule>,
+    #[serde(default)]
+    pub maintenance: Vec<MaintenanceWindow>,
+}
+
+// The durations and times of day are parsed when the settings are loaded, so that an invalid one
+// is reported at startup rather than when it is first used.
+fn duration<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let duration = String::deserialize(deserializer)?;
+    utils::parse_duration(&duration).map_err(de::Error::custom)
+}
+
+fn parse_time_of_day<E: de::Error>(time: &str) -> Result<NaiveTime, E> {
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .map_err(|err| E::custom(format!("Invalid time of day '{}' ({})", time, err)))
+}
+
+fn time_of_day<'de, D>(deserializer: D) -> Result<NaiveTime, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let time = String::deserialize(deserializer)?;
+    parse_time_of_day(&time)
+}
+
+fn optional_time_of_day<'de, D>(deserializer: D) -> Result<Option<NaiveTime>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|time| parse_time_of_day(&time))
+        .transpose()
 }
 
 // TODO Parameterize the config directory
@@ -83,7 +365,7 @@ impl Settings {
         // Add in settings from the environment (with a prefix of APP)
         // Eg.. `APP_DEBUG=1 ./target/app` would set the `debug` key
         config
-            .merge(Environment::with_prefix("app"))
+            .merge(ConfigEnvironment::with_prefix("app"))
             .context(error::ConfigError {
                 details: String::from("Could not merge configuration from environment variables"),
             })?;
@@ -109,8 +391,68 @@ impl Settings {
         }
 
         // You can deserialize (and thus freeze) the entire configuration as
-        config.try_into().context(error::ConfigError {
+        let settings: Settings = config.try_into().context(error::ConfigError {
             details: String::from("Could not generate settings from configuration"),
-        })
+        })?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Reject the settings which deserialize, but which cannot work.
+    pub fn validate(&self) -> Result<(), error::Error> {
+        // A zero interval would make the background tasks panic.
+        for (name, value) in &[
+            ("poller.interval", self.poller.interval),
+            ("poller.timeout", self.poller.timeout),
+            ("retention.interval", self.retention.interval),
+        ] {
+            if *value == 0 {
+                return Err(error::Error::InvalidSettings {
+                    details: format!("{} must be at least 1 second", name),
+                });
+            }
+        }
+        for rule in &self.alerts {
+            if rule.condition.is_transient() && rule.fire_after > 1 {
+                return Err(error::Error::InvalidSettings {
+                    details: format!(
+                        "Alert rule {} ({}) is met by a single probe, and can never fire after {} probes",
+                        rule.name,
+                        rule.condition.kind(),
+                        rule.fire_after
+                    ),
+                });
+            }
+        }
+        if let Some(smtp) = &self.notifications.smtp {
+            if smtp.username.is_some() != smtp.password.is_some() {
+                return Err(error::Error::InvalidSettings {
+                    details: String::from(
+                        "notifications.smtp.username and password must be set together",
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the alert rule with the given name.
+    pub fn alert_rule(&self, name: &str) -> Result<&AlertRule, error::Error> {
+        self.alerts
+            .iter()
+            .find(|rule| rule.name == name)
+            .ok_or_else(|| error::Error::UnknownAlertRule {
+                name: String::from(name),
+            })
+    }
+
+    /// Return the environment with the given name.
+    pub fn environment(&self, name: &str) -> Result<&Environment, error::Error> {
+        self.environments
+            .iter()
+            .find(|env| env.name == name)
+            .ok_or_else(|| error::Error::UnknownEnvironment {
+                name: String::from(name),
+            })
     }
 }
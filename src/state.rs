@@ -1,24 +1,102 @@
+use chrono::{DateTime, TimeZone, Utc};
 use slog::{o, Logger};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use tokio::sync::{broadcast, RwLock};
 
+use crate::api::events::StatusChange;
+use crate::api::index::IndexNaming;
+use crate::api::metrics::Metrics;
+use crate::api::model::{self, BragiInfo};
+use crate::db::Db;
 use crate::error;
 use crate::settings::Settings;
 
+// The number of status changes kept for subscribers lagging behind.
+const CHANGES_CAPACITY: usize = 64;
+
+/// The progress of the background poller, to tell whether it is alive.
+#[derive(Clone, Debug, Default)]
+pub struct Heartbeat {
+    // The times at which the last cycle started and completed, in seconds since the epoch, or 0.
+    started: Arc<AtomicI64>,
+    completed: Arc<AtomicI64>,
+}
+
+impl Heartbeat {
+    pub fn start(&self, at: DateTime<Utc>) {
+        self.started.store(at.timestamp(), Ordering::SeqCst);
+    }
+
+    pub fn complete(&self, at: DateTime<Utc>) {
+        self.completed.store(at.timestamp(), Ordering::SeqCst);
+    }
+
+    /// The time at which the last cycle started, if any.
+    pub fn started(&self) -> Option<DateTime<Utc>> {
+        timestamp(self.started.load(Ordering::SeqCst))
+    }
+
+    /// The time at which the last cycle completed, if any.
+    pub fn completed(&self) -> Option<DateTime<Utc>> {
+        timestamp(self.completed.load(Ordering::SeqCst))
+    }
+}
+
+fn timestamp(seconds: i64) -> Option<DateTime<Utc>> {
+    if seconds > 0 {
+        Some(Utc.timestamp(seconds, 0))
+    } else {
+        None
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct State {
     pub logger: Logger,
     pub settings: Settings,
+    pub naming: IndexNaming,
+    /// The HTTP client of the probes and of the webhooks, shared so that its connections are
+    /// reused.
+    pub client: reqwest::Client,
+    pub db: Db,
+    /// The last status retrieved for each environment, indexed by environment name.
+    pub snapshots: Arc<RwLock<HashMap<String, BragiInfo>>>,
+    /// The statuses which changed since the previous probe of their environment.
+    pub changes: broadcast::Sender<StatusChange>,
+    pub metrics: Metrics,
+    pub heartbeat: Heartbeat,
 }
 
 impl State {
     pub async fn new(settings: &Settings, logger: &Logger) -> Result<Self, error::Error> {
-        let bragi_url = format!("http://{}:{}", settings.bragi.host, settings.bragi.port);
+        let environments = settings
+            .environments
+            .iter()
+            .map(|env| env.name.as_str())
+            .collect::<Vec<_>>()
+            .join(",");
         let logger = logger.new(
-            o!("host" => String::from(&settings.service.host), "port" => settings.service.port, "bragi" => bragi_url),
+            o!("host" => String::from(&settings.service.host), "port" => settings.service.port, "environments" => environments),
         );
 
+        let naming = IndexNaming::new(&settings.indices)?;
+        let client = model::client(settings.poller.timeout)?;
+        let db = Db::new(&settings.database).await?;
+        let (changes, _) = broadcast::channel(CHANGES_CAPACITY);
+        let metrics = Metrics::new()?;
+
         Ok(Self {
             logger,
             settings: settings.clone(),
+            naming,
+            client,
+            db,
+            snapshots: Arc::new(RwLock::new(HashMap::new())),
+            changes,
+            metrics,
+            heartbeat: Heartbeat::default(),
         })
     }
 }
@@ -1,6 +1,14 @@
+use chrono::{DateTime, Duration, Utc};
 use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_TYPE};
+use snafu::ResultExt;
+use std::convert::TryFrom;
 use std::env;
 
+use crate::error;
+
+// The longest duration accepted, in seconds.
+const MAX_DURATION_SECONDS: i64 = 1000 * 365 * 86400;
+
 pub fn construct_headers() -> HeaderMap {
     let mut headers = HeaderMap::new();
     headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
@@ -22,3 +30,51 @@ pub fn get_service_url() -> String {
         _ => format!("http://users:{}/graphql", port),
     }
 }
+
+/// Parse a duration made of a number and a unit, eg '90m', '24h', '30d', '2w'
+pub fn parse_duration(duration: &str) -> Result<Duration, error::Error> {
+    let duration = duration.trim();
+    let split = duration
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(duration.len());
+    let (value, unit) = duration.split_at(split);
+    let value = value.parse::<i64>().context(error::ParseIntError {
+        details: format!("Could not parse duration '{}'", duration),
+    })?;
+    let unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 7 * 86400,
+        _ => {
+            return Err(error::Error::MiscError {
+                details: format!(
+                    "Unknown unit in duration '{}' (expected one of s, m, h, d, w)",
+                    duration
+                ),
+            })
+        }
+    };
+    // chrono panics on durations which do not fit in milliseconds, and dates can only be shifted
+    // by a few thousand centuries, so durations are bounded well below.
+    match value.checked_mul(unit) {
+        Some(seconds) if seconds <= MAX_DURATION_SECONDS => Ok(Duration::seconds(seconds)),
+        _ => Err(error::Error::MiscError {
+            details: format!("Duration '{}' is too long (at most 1000 years)", duration),
+        }),
+    }
+}
+
+/// Return the time 'duration' before 'at', or an error if it is out of the range of dates.
+pub fn time_before(at: DateTime<Utc>, duration: Duration) -> Result<DateTime<Utc>, error::Error> {
+    at.checked_sub_signed(duration)
+        .ok_or_else(|| error::Error::MiscError {
+            details: format!("Could not compute the time {} before {}", duration, at),
+        })
+}
+
+/// Convert a length to a GraphQL count, saturating at i32::MAX.
+pub fn count(len: usize) -> i32 {
+    i32::try_from(len).unwrap_or(i32::MAX)
+}